@@ -0,0 +1,185 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+// runs a scenario without a window and writes how every player is doing
+// as csv, for tuning the balance in batch and for long regression runs.
+// it needs no gui: cargo run --no-default-features --bin simulate -- --years 50
+#![allow(non_upper_case_globals)]
+
+use chrono::Duration;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+use sol::logic::journal::Journal;
+use sol::logic::history::History;
+use sol::logic::model_access::Change;
+use sol::model::scenario::{self, by_name, scenario_names};
+use sol::model::{GameModel, Rules};
+
+const usage: &str = "usage: simulate [--scenario NAME | --scenario-file FILE] [--light-lag] \
+[--set birth_rate=X] [--set mortality=X] [--replay JOURNAL] [--years N | --days N] [--every DAYS] [--out FILE]";
+
+struct Options {
+    scenario: String,
+    // a scenario of our own instead of a built in one
+    scenario_file: Option<String>,
+    rules: Rules,
+    // plays the journal instead, from the scenario it names
+    replay: Option<String>,
+    span: Duration,
+    every: Duration,
+    out: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        scenario: "sol".to_owned(),
+        scenario_file: None,
+        rules: Rules::new(),
+        replay: None,
+        span: Duration::days(365 * 10),
+        every: Duration::days(30),
+        out: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        let number = |x: &String| x.parse::<i64>().map_err(|_| format!("{} is not a number", x));
+        match arg.as_str() {
+            "--scenario" => options.scenario = value()?.clone(),
+            "--scenario-file" => options.scenario_file = Some(value()?.clone()),
+            "--light-lag" => options.rules.light_lag = true,
+            "--set" => options.rules.balance.set(value()?)?,
+            "--replay" => options.replay = Some(value()?.clone()),
+            "--years" => options.span = Duration::days(number(value()?)? * 365),
+            "--days" => options.span = Duration::days(number(value()?)?),
+            "--every" => options.every = Duration::days(number(value()?)?),
+            "--out" => options.out = Some(value()?.clone()),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    if options.every <= Duration::zero() {
+        return Err("--every has to be at least a day".to_owned());
+    }
+    Ok(options)
+}
+
+// one row for each player
+fn write_sample(out: &mut dyn Write, model: &GameModel) -> io::Result<()> {
+    for player in model.players.iter() {
+        let owner = Some(player.id);
+        let population: i64 = model
+            .galaxy
+            .systems
+            .iter()
+            .flat_map(|x| x.bodies.iter())
+            .filter_map(|x| x.get_colony())
+            .filter(|x| x.owner == owner)
+            .filter_map(|x| x.population.as_ref())
+            .map(|x| x.head_count)
+            .sum();
+        let ships = model.ships.values().filter(|x| x.owner == player.id).count();
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            model.time.num_days(),
+            player.id,
+            population,
+            player.money,
+            ships
+        )?;
+    }
+    Ok(())
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let (model, mut tape) = match options.replay {
+        Some(ref path) => {
+            let journal = Journal::read(Path::new(path))?;
+            (journal.start_model()?, Some(journal.tape()))
+        }
+        None => match options.scenario_file {
+            Some(ref path) => (scenario::from_file(Path::new(path), options.rules)?, None),
+            None => {
+                let model = by_name(&options.scenario, options.rules).ok_or(format!(
+                    "no scenario {}, there's {}",
+                    options.scenario,
+                    scenario_names.join(", ")
+                ))?;
+                (model, None)
+            }
+        },
+    };
+    let mut out: Box<dyn Write> = match options.out {
+        Some(ref path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("can't write {}: {}", path, e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let failed = |e: io::Error| e.to_string();
+    let started = Instant::now();
+    writeln!(out, "day,player,population,money,ships").map_err(failed)?;
+    // a replay may rewind, so count what passed instead of looking at the time
+    let mut passed = Duration::zero();
+    let mut current = Arc::new(model);
+    let mut history = History::new(current.clone());
+    loop {
+        let model = Arc::make_mut(&mut current);
+        // colonies only catch up when something happens to them
+        model.settle_all();
+        write_sample(&mut out, model).map_err(failed)?;
+        if passed >= options.span {
+            break;
+        }
+        let step = options.every.min(options.span - passed);
+        passed = passed + step;
+        let change = Change::Time(step);
+        match tape {
+            Some(ref mut tape) => tape.apply(model, &change, &mut |model, change| history.write(model, change)),
+            None => history.write(model, &change),
+        }
+    }
+    out.flush().map_err(failed)?;
+    eprintln!(
+        "simulated {} days of {} in {:.2}s",
+        options.span.num_days(),
+        options
+            .replay
+            .as_ref()
+            .or(options.scenario_file.as_ref())
+            .unwrap_or(&options.scenario),
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|x| x == "--help") {
+        println!("{}", usage);
+        return;
+    }
+    let result = parse_options(&args).and_then(run);
+    if let Err(message) = result {
+        eprintln!("{}\n{}", message, usage);
+        process::exit(1);
+    }
+}
+
@@ -17,29 +17,65 @@
 // does the world to screen mapping, ie determine where a world coordinate
 // (au,au), should be rendered in (px,px)
 
-use conrod::Dimensions;
 use crate::model::galaxy::{Au, BodyAddress};
-use crate::geometry::{Position, Rectangle, Disk};
+use crate::model::bookmark::{Bookmark, Tracking};
+use crate::model::GameModel;
+use crate::geometry::{center, Dimensions, Disk, Position, Rectangle};
 
 pub enum ZoomDirection {
     In,
     Out,
 }
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum MoveDirection {
     Left,
     Right,
     Up,
     Down,
 }
+impl MoveDirection {
+    // in views per second at full speed, the map is mirrored on screen
+    fn movement(&self) -> Position {
+        match *self {
+            MoveDirection::Left => Position::new(scroll_speed, 0.0),
+            MoveDirection::Right => Position::new(-scroll_speed, 0.0),
+            MoveDirection::Up => Position::new(0.0, -scroll_speed),
+            MoveDirection::Down => Position::new(0.0, scroll_speed),
+        }
+    }
+}
 const zoom_factor: f64 = 2.0;
-const move_step: f64 = 0.05;
+const scroll_speed: f64 = 1.0;
+// the gap to where a zoom is going shrinks by a factor e this many times a second
+const zoom_speed: f64 = 12.0;
+// the mouse this close to the edge of the window scrolls
+const edge_pixels: f64 = 12.0;
+// the gap to something we start tracking shrinks as fast as a zoom
+const track_speed: f64 = 8.0;
+// a bit within cannon range
+const min_cam_width: Au = 0.000_01;
+// how far out we can zoom, in widths of the galaxy
+const max_cam_galaxies: f64 = 3.0;
 pub const start_cam_width: Au = 2.0;
 pub const start_cam_height: Au = 2.0;
 pub struct Camera {
     pub position: Position, // position in world coordinates (AU)
     pub width: Au,          // in astromical units
     pub height: Au,
-    pub track_body: Option<BodyAddress>,
+    pub tracking: Option<Tracking>,
+    // where the tracked thing was last frame, we move along with it
+    tracked_at: Option<Position>,
+    // still gliding over to the tracked thing
+    easing: bool,
+    // the keys held down
+    held: Vec<MoveDirection>,
+    // the world point that stays under the cursor while dragging
+    grabbed: Option<Position>,
+    // the width we're zooming to, and where the mouse was, None zooms
+    // around the middle
+    zoom_goal: Option<(Au, Option<Position>)>,
+    // where the camera may look, anywhere if None
+    extent: Option<Rectangle>,
 }
 impl Camera {
     pub fn new(position: Position, width: Au, height: Au) -> Camera {
@@ -47,52 +83,207 @@ impl Camera {
             position: position,
             width: width,
             height: height,
-            track_body: None,
+            tracking: None,
+            tracked_at: None,
+            easing: false,
+            held: Vec::new(),
+            grabbed: None,
+            zoom_goal: None,
+            extent: None,
         }
     }
+    // glides over and keeps it in view, panning looks around it
+    pub fn track(&mut self, target: Tracking) {
+        self.tracking = Some(target);
+        self.tracked_at = None;
+        self.easing = true;
+    }
     pub fn stop_tracking(&mut self) {
-        self.track_body = None;
-    }
-    pub fn translate(&mut self, direction: MoveDirection) {
-        let zero = 0.0;
-        let movement = match direction {
-            MoveDirection::Left => Position::new(move_step, zero),
-            MoveDirection::Right => Position::new(-move_step, zero),
-            MoveDirection::Up => Position::new(zero, -move_step),
-            MoveDirection::Down => Position::new(zero, move_step),
+        self.tracking = None;
+        self.tracked_at = None;
+        self.easing = false;
+    }
+    pub fn tracked_body(&self) -> Option<BodyAddress> {
+        match self.tracking {
+            Some(Tracking::Body(address)) => Some(address),
+            _ => None,
+        }
+    }
+    // moves along with what's tracked, call after update
+    pub fn follow(&mut self, seconds: f64, model: &GameModel) {
+        let target = match self.tracking {
+            Some(tracking) => tracking.position(model),
+            None => return,
+        };
+        let target = match target {
+            Some(x) => x,
+            None => return self.stop_tracking(),
         };
-        let scaled_movement = movement * Position::new(self.width, self.height);
-        self.position += scaled_movement;
+        if let Some(before) = self.tracked_at {
+            let moved = Position::new(target.x - before.x, target.y - before.y);
+            self.position += moved;
+            if let Some(ref mut grabbed) = self.grabbed {
+                *grabbed += moved;
+            }
+        }
+        self.tracked_at = Some(target);
+        if self.easing {
+            let left = (-track_speed * seconds).exp();
+            if target.distance(self.position) * left < self.width * 0.001 {
+                self.position = target;
+                self.easing = false;
+            } else {
+                self.position = target.lerp(self.position, left);
+            }
+        }
+        self.clamp();
+    }
+    // limits panning and zooming to the galaxy
+    pub fn set_extent(&mut self, extent: Rectangle) {
+        self.extent = Some(extent);
+        self.clamp();
+    }
+    // back to where the game started
+    pub fn reset(&mut self) {
+        self.position = center;
+        self.width = start_cam_width;
+        self.height = start_cam_height;
+        self.zoom_goal = None;
         self.stop_tracking();
+        self.clamp();
     }
-    pub fn zoom(
-        &mut self,
-        screen_size: Dimensions,
-        direction: ZoomDirection,
-        mouse_position: Position,
-    ) {
-        let mouse = mouse_position - (Position::arr(screen_size) / Position::i(2));
-        let desired_fixed_point = self.create_projection(screen_size).screen_to_world(mouse);
-
-        match direction {
-            ZoomDirection::In => {
-                self.width /= zoom_factor;
-                self.height /= zoom_factor;
+    // mouse coordinates start top left, conrod's start in the middle with y up
+    pub fn mouse_to_world(&self, screen_size: Dimensions, mouse_position: Position) -> Position {
+        let half = Position::arr(screen_size) / Position::i(2);
+        let mouse = Position::new(mouse_position.x - half.x, half.y - mouse_position.y);
+        self.create_projection(screen_size).screen_to_world(mouse)
+    }
+    // moves the camera so the world point under the mouse goes to the given one
+    fn keep_under_mouse(&mut self, screen_size: Dimensions, mouse: Position, world: Position) {
+        if screen_size[0] <= 0.0 || screen_size[1] <= 0.0 {
+            return;
+        }
+        let now = self.mouse_to_world(screen_size, mouse);
+        self.position = Position::new(
+            self.position.x + world.x - now.x,
+            self.position.y + world.y - now.y,
+        );
+    }
+    pub fn hold(&mut self, direction: MoveDirection, down: bool) {
+        self.held.retain(|x| *x != direction);
+        if down {
+            self.held.push(direction);
+        }
+    }
+    // the window lost focus, we won't hear about keys going up
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.grabbed = None;
+    }
+    pub fn grab(&mut self, screen_size: Dimensions, mouse: Position) {
+        self.grabbed = Some(self.mouse_to_world(screen_size, mouse));
+    }
+    pub fn drag(&mut self, screen_size: Dimensions, mouse: Position) {
+        if let Some(world) = self.grabbed {
+            self.easing = false;
+            self.keep_under_mouse(screen_size, mouse, world);
+            self.clamp();
+        }
+    }
+    pub fn let_go(&mut self) {
+        self.grabbed = None;
+    }
+    // starts zooming, update gets us there
+    pub fn zoom(&mut self, direction: ZoomDirection, mouse_position: Position) {
+        let from = self.zoom_goal.map_or(self.width, |x| x.0);
+        let goal = match direction {
+            ZoomDirection::In => from / zoom_factor,
+            ZoomDirection::Out => from * zoom_factor,
+        };
+        self.zoom_goal = Some((self.clamp_width(goal), Some(mouse_position)));
+    }
+    fn zoom_to(&mut self, width: Au) {
+        self.zoom_goal = Some((self.clamp_width(width), None));
+    }
+    // glides over to it and zooms in or out to see it
+    pub fn go_to(&mut self, target: Tracking, width: Au) {
+        self.track(target);
+        self.zoom_to(width);
+    }
+    pub fn bookmark(&self) -> Bookmark {
+        Bookmark {
+            position: self.position,
+            width: self.zoom_goal.map_or(self.width, |x| x.0),
+            tracking: self.tracking,
+        }
+    }
+    pub fn go_to_bookmark(&mut self, bookmark: &Bookmark) {
+        match bookmark.tracking {
+            Some(tracking) => self.track(tracking),
+            None => {
+                self.stop_tracking();
+                self.position = bookmark.position;
             }
-            ZoomDirection::Out => {
-                self.width *= zoom_factor;
-                self.height *= zoom_factor;
+        }
+        self.zoom_to(bookmark.width);
+    }
+    // whether update would do something, the screen should keep redrawing
+    pub fn is_moving(&self, screen_size: Dimensions, mouse: Option<Position>) -> bool {
+        self.zoom_goal.is_some() || self.easing || !self.directions(screen_size, mouse).is_empty()
+    }
+    fn directions(&self, screen_size: Dimensions, mouse: Option<Position>) -> Vec<MoveDirection> {
+        let mut result = self.held.clone();
+        if let Some(mouse) = mouse {
+            result.extend(edge_directions(screen_size, mouse));
+        }
+        result
+    }
+    // scrolls with the keys and the window edges and animates the zoom,
+    // mouse is None when it's outside the window
+    pub fn update(&mut self, seconds: f64, screen_size: Dimensions, mouse: Option<Position>) {
+        let directions = self.directions(screen_size, mouse);
+        if self.grabbed.is_none() && !directions.is_empty() {
+            let view = Position::new(self.width * seconds, self.height * seconds);
+            for direction in directions {
+                self.position += direction.movement() * view;
             }
+            self.easing = false;
+        }
+        if let Some((goal, anchor)) = self.zoom_goal {
+            let world = anchor.map(|x| self.mouse_to_world(screen_size, x));
+            let gap = (self.width - goal) * (-zoom_speed * seconds).exp();
+            let width = if gap.abs() < goal * 0.001 {
+                self.zoom_goal = None;
+                goal
+            } else {
+                goal + gap
+            };
+            self.height *= width / self.width;
+            self.width = width;
+            if let (Some(anchor), Some(world)) = (anchor, world) {
+                self.keep_under_mouse(screen_size, anchor, world);
+            }
+        }
+        self.clamp();
+    }
+    fn clamp_width(&self, width: Au) -> Au {
+        let widest = self
+            .extent
+            .as_ref()
+            .map_or(f64::MAX, |x| x.width().max(x.height()) * max_cam_galaxies);
+        width.max(min_cam_width).min(widest)
+    }
+    fn clamp(&mut self) {
+        let width = self.clamp_width(self.width);
+        self.height *= width / self.width;
+        self.width = width;
+        if let Some(ref extent) = self.extent {
+            let (low, high) = (extent.tl(), extent.br());
+            self.position = Position::new(
+                self.position.x.max(low.x).min(high.x),
+                self.position.y.max(low.y).min(high.y),
+            );
         }
-
-        let fixed_move = self.create_projection(screen_size).screen_to_world(mouse);
-        let correction_movement = fixed_move - desired_fixed_point;
-
-        println!(
-            "move {}, mouse {}",
-            correction_movement, desired_fixed_point
-        );
-        self.position += correction_movement * Position::new(-1.0, 1.0);
     }
     pub fn create_projection(&self, screen_size: Dimensions) -> Projection {
         let two = 2.0;
@@ -111,6 +302,21 @@ impl Camera {
         }
     }
 }
+// which way the mouse near the edge of the window scrolls
+fn edge_directions(screen_size: Dimensions, mouse: Position) -> Vec<MoveDirection> {
+    let mut result = Vec::new();
+    if mouse.x < edge_pixels {
+        result.push(MoveDirection::Left);
+    } else if mouse.x > screen_size[0] - edge_pixels {
+        result.push(MoveDirection::Right);
+    }
+    if mouse.y < edge_pixels {
+        result.push(MoveDirection::Up);
+    } else if mouse.y > screen_size[1] - edge_pixels {
+        result.push(MoveDirection::Down);
+    }
+    result
+}
 pub struct Projection {
     pub view_port: Rectangle,
     screen_size: Dimensions,
@@ -144,11 +350,16 @@ impl Projection {
 #[cfg(test)]
 mod tests {
     use crate::camera::*;
+    use crate::geometry::center;
+    use crate::model::bookmark::Tracking;
+    use crate::model::galaxy::*;
+    use crate::model::ship::*;
+    use crate::model::*;
     use chrono::Duration;
 
     #[test]
     fn projection_idompotency() {
-        let mut cam = Camera::new(Position { x: 3.0, y: 59.3 }, 4.0, 2.1);
+        let cam = Camera::new(Position { x: 3.0, y: 59.3 }, 4.0, 2.1);
         let some_screensize = [100.0, 250.3]; // floating point may make epsilon differences
         let projection = cam.create_projection(some_screensize);
         let some_point = Position::new(49.0, -239.5);
@@ -184,19 +395,128 @@ mod tests {
                 center,
                 vec![StellarBody::new_earthlike("earth")],
             )]);
-            model.ships = vec![ship];
+            model.ships.insert(ship.id, ship);
             model
         };
-        let mut camera = Camera::new(center, start_cam_width, start_cam_height);
+        let camera = Camera::new(center, start_cam_width, start_cam_height);
         let projection = camera.create_projection([300.0, 600.0]);
 
         let some_visible_days = [45, 135, 215, 305, 345];
         for day in some_visible_days.iter() {
-            let position = gamemodel.ships[0]
+            let position = gamemodel.ships[&0]
                 .movement
                 .calc_position(&Duration::days(*day), &gamemodel.galaxy);
             println!("position {} for day {}", position, day);
             assert!(projection.is_pos_visible(&position))
         }
     }
+    #[test]
+    fn dragging_keeps_the_grabbed_point_under_the_cursor() {
+        let screen = [800.0, 600.0];
+        let mut camera = Camera::new(center, start_cam_width, start_cam_height);
+        let grabbed_at = Position::new(100.0, 400.0);
+        let world = camera.mouse_to_world(screen, grabbed_at);
+        camera.grab(screen, grabbed_at);
+        let dropped_at = Position::new(550.0, 120.0);
+        camera.drag(screen, dropped_at);
+        camera.let_go();
+        let now = camera.mouse_to_world(screen, dropped_at);
+        assert!((now.x - world.x).abs() < 1e-9);
+        assert!((now.y - world.y).abs() < 1e-9);
+    }
+    #[test]
+    fn smooth_zoom_arrives_around_the_mouse() {
+        let screen = [800.0, 600.0];
+        let mut camera = Camera::new(center, start_cam_width, start_cam_height);
+        camera.set_extent(Rectangle {
+            one: Position::new(-40.0, -40.0),
+            two: Position::new(40.0, 40.0),
+        });
+        let mouse = Position::new(200.0, 150.0);
+        let world = camera.mouse_to_world(screen, mouse);
+        camera.zoom(ZoomDirection::In, mouse);
+        camera.zoom(ZoomDirection::In, mouse);
+        camera.update(1.0 / 60.0, screen, None);
+        assert!(camera.width < start_cam_width);
+        assert!(camera.width > start_cam_width / 4.0);
+        for _ in 0..60 {
+            camera.update(1.0 / 60.0, screen, None);
+        }
+        assert_eq!(camera.width, start_cam_width / 4.0);
+        let now = camera.mouse_to_world(screen, mouse);
+        assert!((now.x - world.x).abs() < 1e-9);
+        assert!((now.y - world.y).abs() < 1e-9);
+        // not further out than a few galaxies
+        for _ in 0..20 {
+            camera.zoom(ZoomDirection::Out, mouse);
+        }
+        for _ in 0..120 {
+            camera.update(1.0 / 60.0, screen, None);
+        }
+        assert_eq!(camera.width, 240.0);
+    }
+    #[test]
+    fn tracking_glides_over_and_follows_along() {
+        let earth = BodyAddress {
+            system_id: 0,
+            planet_id: 3,
+        };
+        let mut model = crate::model::scenario::sol();
+        let mut camera = Camera::new(center, start_cam_width, start_cam_height);
+        camera.track(Tracking::Body(earth));
+        camera.follow(1.0 / 60.0, &model);
+        let target = Tracking::Body(earth).position(&model).unwrap();
+        assert!(camera.position.distance(target) > 0.0);
+        assert!(camera.position.distance(target) < center.distance(target));
+        for _ in 0..60 {
+            camera.follow(1.0 / 60.0, &model);
+        }
+        assert_eq!(camera.position, target);
+
+        // panning looks around it, we keep following from there
+        camera.hold(MoveDirection::Left, true);
+        camera.update(0.5, [800.0, 600.0], None);
+        camera.hold(MoveDirection::Left, false);
+        camera.follow(0.5, &model);
+        let offset = camera.position.x - target.x;
+        assert!(offset > 0.0);
+        model.time = model.time + Duration::weeks(1);
+        camera.follow(1.0 / 60.0, &model);
+        let target = Tracking::Body(earth).position(&model).unwrap();
+        assert!((camera.position.x - target.x - offset).abs() < 1e-9);
+        assert_eq!(camera.tracking, Some(Tracking::Body(earth)));
+
+        // ships that are gone can't be followed
+        camera.track(Tracking::Ship(3));
+        camera.follow(1.0 / 60.0, &model);
+        assert_eq!(camera.tracking, None);
+    }
+    #[test]
+    fn bookmarks_bring_the_view_back() {
+        let screen = [800.0, 600.0];
+        let model = crate::model::scenario::sol();
+        let mars = Tracking::Body(BodyAddress {
+            system_id: 0,
+            planet_id: 4,
+        });
+        let mut camera = Camera::new(center, start_cam_width, start_cam_height);
+        camera.go_to(mars, mars.view_width(&model));
+        for _ in 0..60 {
+            camera.update(1.0 / 60.0, screen, None);
+            camera.follow(1.0 / 60.0, &model);
+        }
+        let saved = camera.bookmark();
+        assert_eq!(saved.tracking, Some(mars));
+        assert_eq!(saved.position, mars.position(&model).unwrap());
+        assert_eq!(saved.width, mars.view_width(&model));
+
+        camera.reset();
+        camera.go_to_bookmark(&saved);
+        for _ in 0..60 {
+            camera.update(1.0 / 60.0, screen, None);
+            camera.follow(1.0 / 60.0, &model);
+        }
+        assert_eq!(camera.bookmark(), saved);
+        assert!(!camera.is_moving(screen, None));
+    }
 }
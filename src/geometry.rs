@@ -24,7 +24,8 @@ pub struct Position {
     // top = 0
     pub y: f64,
 }
-use conrod::Dimensions;
+// width and height, the same as conrod's so the gui can pass its own
+pub type Dimensions = [f64; 2];
 use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 impl Position {
     pub fn new(x: f64, y: f64) -> Position {
@@ -51,6 +52,18 @@ impl Position {
     fn angle_rad(&self, other: Position) -> f64 {
         (self.y - other.y).atan2(self.x - other.x)
     }
+    pub fn distance(&self, other: Position) -> f64 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        (dx * dx + dy * dy).sqrt()
+    }
+    // walk fraction of the way from self to other, 0.0 is self, 1.0 is other
+    pub fn lerp(&self, other: Position, fraction: f64) -> Position {
+        Position {
+            x: self.x + (other.x - self.x) * fraction,
+            y: self.y + (other.y - self.y) * fraction,
+        }
+    }
 }
 use std::fmt;
 impl fmt::Display for Position {
@@ -225,7 +238,7 @@ pub const center: Position = Position { x: 0.0, y: 0.0 };
 
 #[cfg(test)]
 mod tests {
-    use geometry::{Position, Rectangle};
+    use crate::geometry::{Position, Rectangle};
     #[test]
     fn rectangle_contains() {
         let point = Position::i(6);
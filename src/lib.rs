@@ -0,0 +1,29 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+// the game as a library, the model and the threads that run it need no
+// window. the screens are behind the gui feature, so tools and servers can
+// use default-features = false and link without opengl.
+#![allow(non_upper_case_globals)]
+
+pub mod camera;
+pub mod geometry;
+pub mod logic;
+pub mod model;
+#[cfg(feature = "gui")]
+pub mod state;
+#[cfg(feature = "gui")]
+pub mod view;
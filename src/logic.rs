@@ -22,3 +22,7 @@ pub mod thread_status;
 pub mod logic_updater;
 pub mod model_access;
 pub mod pulser;
+pub mod simulation;
+pub mod journal;
+pub mod history;
+pub mod forecast;
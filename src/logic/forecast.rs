@@ -0,0 +1,219 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the forecaster. it takes a copy of the latest snapshot
+// and lets it run on its own for some months, the same way the writer would
+// if nobody did anything. the live game is never touched, the screens only
+// get to read the last forecast that's done.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+use arc_swap::ArcSwapOption;
+use chrono::Duration;
+
+use crate::model::GameModel;
+use crate::model::galaxy::{BodyAddress, BodyClass};
+use super::model_access::{Change, ModelAccess};
+use super::thread_status::{Status, ThreadControll};
+
+pub fn months(count:i64) -> Duration{
+    Duration::days(365 * count) / 12
+}
+// a running game publishes a snapshot every tick, the next forecast waits
+// at least this long after the last one started
+const restart_after_ms:u64 = 2000;
+// how precise the construction dates are
+fn forecast_step() -> Duration{
+    Duration::days(1)
+}
+
+pub struct Forecast{
+    // the snapshot it was made from
+    source:Arc<GameModel>,
+    pub from:Duration,
+    pub until:Duration,
+    // head count of every colony at the end
+    pub population:BTreeMap<BodyAddress, i64>,
+    // money of every player at the end
+    pub money:Vec<i64>,
+    // when what's queued now at the colony is done, None if it isn't by the
+    // end, in the order of the queue
+    pub completions:BTreeMap<BodyAddress, Vec<Option<Duration>>>,
+}
+impl Forecast{
+    pub fn of(source:Arc<GameModel>, horizon:Duration) -> Forecast{
+        Forecast::until_stopped(source, horizon, &|| false).expect("never stopped")
+    }
+    // None if it was stopped before it was done
+    fn until_stopped(source:Arc<GameModel>, horizon:Duration, stopped:&dyn Fn() -> bool) -> Option<Forecast>{
+        let mut model = (*source).clone();
+        let from = model.time;
+        let until = from + horizon;
+        let mut waiting:Vec<(BodyAddress, usize, _)> = model.galaxy.systems.iter()
+            .flat_map(|x| x.bodies.iter())
+            .filter_map(|x| x.get_colony().map(|colony| (x.address, colony)))
+            .flat_map(|(address, colony)| colony.construction_queue.iter().enumerate()
+                .map(move |(i, job)| (address, i, job.constructable.clone())))
+            .collect();
+        let mut completions:BTreeMap<BodyAddress, Vec<Option<Duration>>> = BTreeMap::new();
+        for &(address, _, _) in waiting.iter(){
+            completions.entry(address).or_default().push(None);
+        }
+        while model.time < until {
+            if stopped() {
+                return None;
+            }
+            let step = forecast_step().min(until - model.time);
+            ModelAccess::write(&mut model, &Change::Time(step));
+            let time = model.time;
+            let galaxy = &model.galaxy;
+            waiting.retain(|&(address, i, ref constructable)| {
+                let queued = galaxy[address].get_colony().is_some_and(|colony| {
+                    colony.construction_queue.iter().any(|x| Arc::ptr_eq(&x.constructable, constructable))
+                });
+                if !queued {
+                    completions.get_mut(&address).expect("added above")[i] = Some(time);
+                }
+                queued
+            });
+        }
+        model.settle_all();
+        let population = model.galaxy.systems.iter()
+            .flat_map(|x| x.bodies.iter())
+            .filter_map(|body| match body.class {
+                BodyClass::Rocky(ref colony) => colony.population.as_ref().map(|x| (body.address, x.head_count)),
+                _ => None
+            }).collect();
+        Some(Forecast{
+            money:model.players.iter().map(|x| x.money).collect(),
+            source,
+            from,
+            until,
+            population,
+            completions,
+        })
+    }
+    // the first of the colony's constructions that's done, from the given time
+    pub fn next_ready(&self, address:BodyAddress, now:Duration) -> Option<Duration>{
+        self.completions.get(&address)?.iter().filter_map(|x| *x).min()
+            .map(|x| (x - now).max(Duration::zero()))
+    }
+}
+
+// works on the latest snapshot whenever there's a new one, looking the
+// given number of months ahead
+pub struct Forecaster{
+    pub controll:ThreadControll,
+    pub latest:Arc<ArcSwapOption<Forecast>>,
+    pub horizon:Duration,
+}
+impl Forecaster{
+    pub fn new(horizon_months:i64) -> Forecaster{
+        let mut controll = ThreadControll::new();
+        // a forecast takes a while, no need to look for work all the time
+        controll.set_pace(250);
+        Forecaster{
+            controll,
+            latest:Arc::new(ArcSwapOption::empty()),
+            horizon:months(horizon_months),
+        }
+    }
+    pub fn start(&self, model:ModelAccess) -> JoinHandle<()> {
+        let latest = self.latest.clone();
+        let controll = self.controll.clone();
+        let horizon = self.horizon;
+        let started:Cell<Option<Instant>> = Cell::new(None);
+        self.controll.execute_logic(move ||{
+            let snapshot = model.snapshot();
+            let done = latest.load().as_ref().is_some_and(|x| Arc::ptr_eq(&x.source, &snapshot));
+            if done {
+                return;
+            }
+            let recently = started.get().is_some_and(|x| {
+                x.elapsed() < std::time::Duration::from_millis(restart_after_ms)
+            });
+            if recently {
+                return;
+            }
+            started.set(Some(Instant::now()));
+            // months of game time, shutting down shouldn't wait for that
+            let stopped = || controll.get_status() == Status::Aborted;
+            if let Some(forecast) = Forecast::until_stopped(snapshot, horizon, &stopped) {
+                latest.store(Some(Arc::new(forecast)));
+            }
+        })
+    }
+    pub fn latest(&self) -> Option<Arc<Forecast>>{
+        self.latest.load_full()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::forecast::{Forecast, Forecaster};
+    use crate::logic::model_access::{Change, ModelAccess};
+    use crate::model::galaxy::BodyAddress;
+    use crate::model::scenario;
+    use crate::model::ship::Ship;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    #[test]
+    fn forecast_sees_the_future_without_changing_the_present() {
+        let earth = BodyAddress {
+            system_id: 0,
+            planet_id: 3,
+        };
+        let mut model = scenario::sol();
+        ModelAccess::write(&mut model, &Change::Construct(Arc::new(Ship::new(0, 1000, earth)), earth));
+        ModelAccess::write(&mut model, &Change::Time(Duration::weeks(1)));
+        let live = Arc::new(model);
+        let forecast = Forecast::of(live.clone(), Duration::weeks(52));
+
+        assert_eq!(live.time, Duration::weeks(1));
+        assert!(live.ships.is_empty());
+        // a ship takes four weeks, one's done already
+        let ready = forecast.next_ready(earth, live.time).unwrap();
+        assert!(ready >= Duration::weeks(3) - Duration::days(1));
+        assert!(ready <= Duration::weeks(3) + Duration::days(1));
+        assert!(forecast.population[&earth] > live.galaxy[earth].get_colony().unwrap().population.as_ref().unwrap().head_count);
+        assert!(forecast.money[0] > live.players[0].money);
+    }
+
+    #[test]
+    fn unchanged_snapshot_is_forecast_once() {
+        let access = ModelAccess::new(scenario::sol());
+        let mut forecaster = Forecaster::new(1);
+        let thread = forecaster.start(access.clone());
+        let mut waited = 0;
+        while forecaster.latest().is_none() {
+            assert!(waited < 10_000, "no forecast came");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            waited += 1;
+        }
+        let first = forecaster.latest().unwrap();
+        assert_eq!(first.until - first.from, Duration::days(30) + Duration::hours(10));
+        // a couple of rounds without a new snapshot
+        std::thread::sleep(std::time::Duration::from_millis(600));
+        assert!(Arc::ptr_eq(&first, &forecaster.latest().unwrap()));
+        forecaster.controll.stop();
+        thread.join().expect("forecaster didn't panic");
+    }
+}
@@ -0,0 +1,190 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the history the writer keeps for rewinding. on every
+// multiple of the snapshot interval it holds on to a copy of the model, with
+// the changes applied since. time changes are split on those moments, so a
+// recording and its replay keep the same snapshots. going back to a moment starts from the snapshot before it
+// and applies the changes untill then again, the same way a journal is
+// replayed. whatever came after is forgotten, the game branches off there.
+// snapshots share their systems with each other, so they're cheap.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use chrono::Duration;
+
+use crate::model::GameModel;
+use super::journal::{game_time, Entry, Tape};
+use super::model_access::{Change, ModelAccess};
+
+// game time between snapshots
+pub fn snapshot_every() -> Duration{
+    Duration::weeks(1)
+}
+// a year back at most
+pub const history_size:usize = 52;
+
+struct Frame{
+    model:Arc<GameModel>,
+    // applied after the snapshot, time isn't in here, it's in the stamps
+    changes:Vec<Entry>,
+}
+
+pub struct History{
+    // oldest first
+    frames:VecDeque<Frame>,
+}
+impl History{
+    pub fn new(start:Arc<GameModel>) -> History{
+        let mut frames = VecDeque::with_capacity(history_size);
+        frames.push_back(Frame{model:start, changes:Vec::new()});
+        History{frames}
+    }
+    // applies the change to the model and remembers it, rewinds go back
+    pub fn write(&mut self, model:&mut GameModel, change:&Change){
+        match *change {
+            Change::Rewind(to) => *model = self.rewind(to),
+            Change::Time(increase) => {
+                let every = snapshot_every().num_milliseconds();
+                let mut left = increase;
+                while left > Duration::zero() {
+                    let now = game_time(model);
+                    let next = Duration::milliseconds((now.num_milliseconds().div_euclid(every) + 1) * every);
+                    if now + left < next {
+                        ModelAccess::write(model, &Change::Time(left));
+                        break;
+                    }
+                    ModelAccess::write(model, &Change::Time(next - now));
+                    left = left - (next - now);
+                    self.snapshot(model);
+                }
+            }
+            _ => {
+                if let Some(frame) = self.frames.back_mut() {
+                    frame.changes.push(Entry{time:game_time(model), change:change.clone()});
+                }
+                ModelAccess::write(model, change);
+            }
+        }
+    }
+    // holds on to the model as it is now, the systems stay shared
+    fn snapshot(&mut self, model:&GameModel){
+        if self.frames.len() >= history_size {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Frame{model:Arc::new(model.clone()), changes:Vec::new()});
+    }
+    // the earliest moment we can still go back to
+    pub fn earliest(&self) -> Duration{
+        self.frames.front().map_or(Duration::zero(), |x| game_time(&x.model))
+    }
+    // the game as it was at the given time, or as early as we remember
+    fn rewind(&mut self, to:Duration) -> GameModel{
+        let to = to.max(self.earliest());
+        while self.frames.len() > 1 && self.frames.back().is_some_and(|x| game_time(&x.model) > to) {
+            self.frames.pop_back();
+        }
+        let frame = self.frames.back_mut().expect("the first frame is never dropped by a rewind");
+        let mut model = (*frame.model).clone();
+        frame.changes.retain(|x| x.time <= to);
+        let mut tape = Tape::Play(frame.changes.clone().into());
+        tape.apply(&mut model, &Change::Time(to - game_time(&frame.model)), &mut ModelAccess::write);
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::logic::history::{snapshot_every, History};
+    use crate::logic::journal::game_time;
+    use crate::logic::model_access::Change;
+    use crate::model::galaxy::BodyAddress;
+    use crate::model::order::Order;
+    use crate::model::scenario;
+    use crate::model::ship::Ship;
+    use crate::model::GameModel;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    #[test]
+    fn rewinding_branches_off_the_past() {
+        let earth = BodyAddress {
+            system_id: 0,
+            planet_id: 3,
+        };
+        let mut current = Arc::new(scenario::sol());
+        let mut history = History::new(current.clone());
+        let run = |history: &mut History, current: &mut Arc<GameModel>, change: Change| {
+            history.write(Arc::make_mut(current), &change);
+        };
+        run(&mut history, &mut current, Change::Construct(Arc::new(Ship::new(0, 1000, earth)), earth));
+        for _ in 0..(7 * 5) {
+            run(&mut history, &mut current, Change::Time(Duration::days(1)));
+        }
+        run(&mut history, &mut current, Change::Select(0, vec![0]));
+        let moving = Order::MoveTo(Position::new(3.0, 0.0));
+        run(&mut history, &mut current, Change::Order(0, moving, false));
+        for _ in 0..10 {
+            run(&mut history, &mut current, Change::Time(Duration::hours(20)));
+        }
+        let before = (*current).clone();
+        let until = current.time + current.pending_time;
+        for _ in 0..(7 * 3) {
+            run(&mut history, &mut current, Change::Time(Duration::days(1)));
+        }
+        // a different future
+        run(&mut history, &mut current, Change::Order(0, Order::Orbit(earth), false));
+
+        run(&mut history, &mut current, Change::Rewind(until));
+        assert_eq!(current.time, before.time);
+        assert_eq!(current.pending_time, before.pending_time);
+        assert_eq!(current.players[0].money, before.players[0].money);
+        let position = |x: &GameModel| x.ships[&0].movement.calc_position(&until, &x.galaxy);
+        assert_eq!(position(&current), position(&before));
+
+        // further back than remembered goes to the start
+        run(&mut history, &mut current, Change::Rewind(Duration::weeks(-3)));
+        assert_eq!(current.time, Duration::zero());
+        assert!(current.ships.is_empty());
+    }
+
+    #[test]
+    fn snapshots_fall_on_the_same_moments_whatever_the_batches() {
+        let record = |batch: Duration| {
+            let mut current = Arc::new(scenario::sol());
+            let mut history = History::new(current.clone());
+            let mut passed = Duration::zero();
+            while passed < Duration::weeks(5) {
+                history.write(Arc::make_mut(&mut current), &Change::Time(batch));
+                passed = passed + batch;
+            }
+            history
+                .frames
+                .iter()
+                .map(|x| (game_time(&x.model), x.model.players[0].money))
+                .collect::<Vec<_>>()
+        };
+        let daily = record(Duration::days(1));
+        assert_eq!(daily, record(Duration::hours(77)));
+        assert_eq!(daily, record(Duration::days(9)));
+        assert_eq!(daily.len(), 6);
+        for (n, (time, _)) in daily.iter().enumerate() {
+            assert_eq!(*time, snapshot_every() * n as i32);
+        }
+    }
+}
@@ -0,0 +1,496 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the journal, every change the writer applies is
+// written down with the game time it was applied at. the game is the same
+// every time for the same changes at the same times, so the scenario it
+// started from and the journal are enough to play it again exactly.
+// time itself isn't written down, it's in the stamps. one line per change:
+//
+//     scenario sol
+//     rules light_lag birth_rate=0.12
+//     86400000 order 0 0 move 1.5 -0.25
+//     90000000 construct 0 3 ship 0 1500 sensor=0.5 survey
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+use std::sync::Arc;
+use chrono::Duration;
+
+use crate::geometry::Position;
+use crate::model::{GameModel, Rules};
+use crate::model::galaxy::BodyAddress;
+use crate::model::combat::Target;
+use crate::model::order::Order;
+use crate::model::bookmark::{Bookmark, Tracking};
+use crate::model::ship::Ship;
+use crate::model::station::Station;
+use crate::model::scenario;
+use super::model_access::Change;
+
+#[derive(Clone)]
+pub struct Entry{
+    pub time:Duration,
+    pub change:Change,
+}
+
+// the game time including what didn't fill a simulation step yet
+pub fn game_time(model:&GameModel) -> Duration{
+    model.time + model.pending_time
+}
+
+// what the writer does with the changes it gets
+pub enum Tape{
+    // every change is written down as it's applied
+    Record(JournalWriter),
+    // the journal is applied as time passes, changes from the screens are
+    // ignored so the game goes the way it went
+    Play(VecDeque<Entry>),
+}
+impl Tape{
+    // write is what applies a change to the model, with or without history
+    pub fn apply(&mut self, model:&mut GameModel, change:&Change, write:&mut dyn FnMut(&mut GameModel, &Change)){
+        match *self {
+            Tape::Record(ref mut journal) => {
+                if let Change::Time(_) = *change {} else {
+                    journal.record(game_time(model), change);
+                }
+                write(model, change);
+            }
+            Tape::Play(ref mut entries) => {
+                let mut left = match *change {
+                    Change::Time(increase) => increase,
+                    _ => return
+                };
+                // after a rewind the stamps start over from further back,
+                // so this counts the time that passed and not where it ends
+                while let Some(gap) = entries.front().map(|x| x.time - game_time(model)) {
+                    if gap > left {
+                        break;
+                    }
+                    if gap > Duration::zero() {
+                        write(model, &Change::Time(gap));
+                        left = left - gap;
+                    }
+                    let entry = entries.pop_front().unwrap();
+                    write(model, &entry.change);
+                }
+                write(model, &Change::Time(left));
+            }
+        }
+    }
+    // so a crash doesn't lose what happened right before it
+    pub fn flush(&mut self){
+        if let Tape::Record(ref mut journal) = *self {
+            journal.flush();
+        }
+    }
+    pub fn is_playing(&self) -> bool{
+        match *self {
+            Tape::Play(_) => true,
+            Tape::Record(_) => false,
+        }
+    }
+}
+
+pub struct JournalWriter{
+    // gone after the first error, the game goes on without a journal
+    out:Option<BufWriter<File>>,
+}
+impl JournalWriter{
+    pub fn create(path:&Path, scenario:&str, rules:Rules) -> io::Result<JournalWriter>{
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "scenario {}", scenario)?;
+        let mut written = rules.balance.changed();
+        if rules.light_lag {
+            written.insert(0, "light_lag".to_owned());
+        }
+        writeln!(out, "rules{}", written.iter().map(|x| format!(" {}", x)).collect::<String>())?;
+        Ok(JournalWriter{out:Some(out)})
+    }
+    pub fn record(&mut self, time:Duration, change:&Change){
+        let result = match self.out {
+            Some(ref mut out) => writeln!(out, "{} {}", time.num_milliseconds(), encode_change(change)),
+            None => return
+        };
+        self.failed(result);
+    }
+    pub fn flush(&mut self){
+        let result = match self.out {
+            Some(ref mut out) => out.flush(),
+            None => return
+        };
+        self.failed(result);
+    }
+    fn failed(&mut self, result:io::Result<()>){
+        if let Err(error) = result {
+            println!("stopped the journal: {}", error);
+            self.out = None;
+        }
+    }
+}
+
+pub struct Journal{
+    pub scenario:String,
+    pub rules:Rules,
+    pub entries:Vec<Entry>,
+}
+impl Journal{
+    pub fn read(path:&Path) -> Result<Journal, String>{
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Journal::parse(&text)
+    }
+    pub fn parse(text:&str) -> Result<Journal, String>{
+        let mut lines = text.lines().enumerate();
+        let mut header = |expected:&str| -> Result<Vec<&str>, String>{
+            let (_, line) = lines.next().ok_or("the journal is empty".to_owned())?;
+            let mut words = line.split_whitespace();
+            if words.next() != Some(expected) {
+                return Err(format!("the journal should start with {}", expected));
+            }
+            Ok(words.collect())
+        };
+        let scenario = match header("scenario")?.as_slice() {
+            &[name] => name.to_owned(),
+            _ => return Err("the scenario should be one word".to_owned())
+        };
+        let mut rules = Rules::new();
+        for rule in header("rules")? {
+            match rule {
+                "light_lag" => rules.light_lag = true,
+                _ if rule.contains('=') => rules.balance.set(rule)?,
+                _ => return Err(format!("unknown rule {}", rule))
+            }
+        }
+        let entries = lines.filter(|x| !x.1.trim().is_empty())
+            .map(|(number, line)| decode_entry(line).map_err(|e| format!("line {}: {}", number + 1, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Journal{
+            scenario,
+            rules,
+            entries,
+        })
+    }
+    // the game as it was when the journal started
+    pub fn start_model(&self) -> Result<GameModel, String>{
+        scenario::by_name(&self.scenario, self.rules).ok_or(format!("no scenario {}", self.scenario))
+    }
+    pub fn tape(self) -> Tape{
+        Tape::Play(self.entries.into())
+    }
+}
+
+fn address_words(address:BodyAddress) -> String{
+    format!("{} {}", address.system_id, address.planet_id)
+}
+fn optional_address_words(address:Option<BodyAddress>) -> String{
+    address.map_or("none".to_owned(), address_words)
+}
+fn target_words(target:Target) -> String{
+    match target {
+        Target::Ship(ship) => format!("ship {}", ship),
+        Target::Station(station) => format!("station {}", station),
+    }
+}
+fn tracking_words(tracking:Option<Tracking>) -> String{
+    match tracking {
+        None => "none".to_owned(),
+        Some(Tracking::Body(address)) => format!("body {}", address_words(address)),
+        Some(Tracking::Ship(id)) => format!("ship {}", id),
+        Some(Tracking::Fleet(id)) => format!("fleet {}", id),
+        Some(Tracking::System(id)) => format!("system {}", id),
+    }
+}
+fn order_words(order:&Order) -> String{
+    match *order {
+        Order::MoveTo(to) => format!("move {} {}", to.x, to.y),
+        Order::Orbit(address) => format!("orbit {}", address_words(address)),
+        Order::Patrol(ref waypoints, next) => {
+            let waypoints:String = waypoints.iter().map(|x| format!(" {} {}", x.x, x.y)).collect();
+            format!("patrol {}{}", next, waypoints)
+        }
+        Order::Escort(ship) => format!("escort {}", ship),
+        Order::Refuel(at) => format!("refuel {}", optional_address_words(at)),
+        Order::Loop => "loop".to_owned(),
+        Order::Explore => "explore".to_owned(),
+        Order::Invade(address) => format!("invade {}", address_words(address)),
+        Order::Attack(target) => format!("attack {}", target_words(target)),
+        Order::LayMines(at) => format!("lay_mines {}", optional_address_words(at)),
+        Order::FireMissiles(target) => format!("fire_missiles {}", target_words(target)),
+    }
+}
+
+pub fn encode_change(change:&Change) -> String{
+    match *change {
+        Change::Construct(ref constructable, address) =>
+            format!("construct {} {}", address_words(address), constructable.journal()),
+        Change::Select(player, ref selected) => {
+            let ships:String = selected.iter().map(|x| format!(" {}", x)).collect();
+            format!("select {}{}", player, ships)
+        }
+        Change::Time(increase) => format!("time {}", increase.num_milliseconds()),
+        Change::Rewind(to) => format!("rewind {}", to.num_milliseconds()),
+        Change::FormFleet(player) => format!("form_fleet {}", player),
+        Change::MergeFleets(player) => format!("merge_fleets {}", player),
+        Change::SplitFleet(player) => format!("split_fleet {}", player),
+        Change::Order(player, ref order, queued) =>
+            format!("order {} {} {}", player, queued as u8, order_words(order)),
+        Change::Bookmark(player, slot, ref bookmark) => format!("bookmark {} {} {} {} {} {}",
+            player, slot, bookmark.position.x, bookmark.position.y, bookmark.width, tracking_words(bookmark.tracking)),
+    }
+}
+
+fn decode_entry(line:&str) -> Result<Entry, String>{
+    let mut words = Words(line.split_whitespace());
+    let time = Duration::milliseconds(words.number()?);
+    Ok(Entry{time, change:decode_words(words)?})
+}
+
+pub fn decode_change(text:&str) -> Result<Change, String>{
+    decode_words(Words(text.split_whitespace()))
+}
+
+struct Words<'a>(SplitWhitespace<'a>);
+impl<'a> Words<'a>{
+    fn word(&mut self) -> Result<&'a str, String>{
+        self.0.next().ok_or("the line ends too soon".to_owned())
+    }
+    fn number<T:FromStr>(&mut self) -> Result<T, String>{
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("{} is not a number", word))
+    }
+    fn address(&mut self) -> Result<BodyAddress, String>{
+        Ok(BodyAddress{system_id:self.number()?, planet_id:self.number()?})
+    }
+    fn optional_address(&mut self) -> Result<Option<BodyAddress>, String>{
+        let word = self.word()?;
+        if word == "none" {
+            return Ok(None);
+        }
+        let system_id = word.parse().map_err(|_| format!("{} is not a number", word))?;
+        Ok(Some(BodyAddress{system_id, planet_id:self.number()?}))
+    }
+    fn position(&mut self) -> Result<Position, String>{
+        Ok(Position::new(self.number()?, self.number()?))
+    }
+    fn target(&mut self) -> Result<Target, String>{
+        match self.word()? {
+            "ship" => Ok(Target::Ship(self.number()?)),
+            "station" => Ok(Target::Station(self.number()?)),
+            other => Err(format!("{} can't be a target", other))
+        }
+    }
+    fn tracking(&mut self) -> Result<Option<Tracking>, String>{
+        Ok(Some(match self.word()? {
+            "none" => return Ok(None),
+            "body" => Tracking::Body(self.address()?),
+            "ship" => Tracking::Ship(self.number()?),
+            "fleet" => Tracking::Fleet(self.number()?),
+            "system" => Tracking::System(self.number()?),
+            other => return Err(format!("can't follow a {}", other))
+        }))
+    }
+    fn order(&mut self) -> Result<Order, String>{
+        Ok(match self.word()? {
+            "move" => Order::MoveTo(self.position()?),
+            "orbit" => Order::Orbit(self.address()?),
+            "patrol" => {
+                let next = self.number()?;
+                let mut waypoints = Vec::new();
+                while let Some(x) = self.0.next() {
+                    let x = x.parse().map_err(|_| format!("{} is not a number", x))?;
+                    waypoints.push(Position::new(x, self.number()?));
+                }
+                Order::Patrol(waypoints, next)
+            }
+            "escort" => Order::Escort(self.number()?),
+            "refuel" => Order::Refuel(self.optional_address()?),
+            "loop" => Order::Loop,
+            "explore" => Order::Explore,
+            "invade" => Order::Invade(self.address()?),
+            "attack" => Order::Attack(self.target()?),
+            "lay_mines" => Order::LayMines(self.optional_address()?),
+            "fire_missiles" => Order::FireMissiles(self.target()?),
+            other => return Err(format!("unknown order {}", other))
+        })
+    }
+    fn rest(self) -> Vec<&'a str>{
+        self.0.collect()
+    }
+}
+
+fn decode_words(mut words:Words) -> Result<Change, String>{
+    Ok(match words.word()? {
+        "construct" => {
+            let address = words.address()?;
+            let kind = words.word()?;
+            let rest = words.rest();
+            match kind {
+                "ship" => Change::Construct(Arc::new(Ship::from_journal(&rest, address)
+                    .ok_or("that's not a ship".to_owned())?), address),
+                "station" => Change::Construct(Arc::new(Station::from_journal(&rest, address)
+                    .ok_or("that's not a station".to_owned())?), address),
+                other => return Err(format!("can't construct a {}", other))
+            }
+        }
+        "select" => {
+            let player = words.number()?;
+            let ships = words.rest().iter().map(|x| x.parse().map_err(|_| format!("{} is not a ship", x)))
+                .collect::<Result<_, _>>()?;
+            Change::Select(player, ships)
+        }
+        "time" => Change::Time(Duration::milliseconds(words.number()?)),
+        "rewind" => Change::Rewind(Duration::milliseconds(words.number()?)),
+        "form_fleet" => Change::FormFleet(words.number()?),
+        "merge_fleets" => Change::MergeFleets(words.number()?),
+        "split_fleet" => Change::SplitFleet(words.number()?),
+        "order" => {
+            let player = words.number()?;
+            let queued = words.number::<u8>()? != 0;
+            Change::Order(player, words.order()?, queued)
+        }
+        "bookmark" => {
+            let player = words.number()?;
+            let slot = words.number()?;
+            let bookmark = Bookmark{
+                position:words.position()?,
+                width:words.number()?,
+                tracking:words.tracking()?,
+            };
+            Change::Bookmark(player, slot, bookmark)
+        }
+        other => return Err(format!("unknown change {}", other))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::logic::journal::*;
+    use crate::logic::model_access::{Change, ModelAccess};
+    use crate::model::combat::Target;
+    use crate::model::galaxy::BodyAddress;
+    use crate::model::order::Order;
+    use crate::model::scenario;
+    use crate::model::ship::{Component, Ship};
+    use crate::model::station::{Anchor, Station, StationKind};
+    use crate::model::Rules;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    const earth: BodyAddress = BodyAddress {
+        system_id: 0,
+        planet_id: 3,
+    };
+
+    #[test]
+    fn changes_read_back_as_written() {
+        let changes = vec![
+            Change::Construct(
+                Arc::new(Ship::new(0, 1500, earth).with_component(Component::Troops(2500.5))),
+                earth,
+            ),
+            Change::Construct(
+                Arc::new(Station::new(1, 4000, StationKind::Depot, Anchor::Orbit, earth)),
+                earth,
+            ),
+            Change::Select(0, vec![3, 1, 4]),
+            Change::Order(0, Order::MoveTo(Position::new(0.1, -1e-7)), true),
+            Change::Order(0, Order::Patrol(vec![Position::new(1.0, 2.0), Position::new(3.0, 4.0)], 1), false),
+            Change::Order(0, Order::Refuel(None), false),
+            Change::Order(0, Order::LayMines(Some(earth)), false),
+            Change::Order(0, Order::FireMissiles(Target::Station(2)), false),
+            Change::SplitFleet(1),
+            Change::Rewind(Duration::days(3)),
+            Change::Bookmark(0, 2, Bookmark {
+                position: Position::new(-0.5, 1e-3),
+                width: 0.25,
+                tracking: Some(Tracking::Body(earth)),
+            }),
+            Change::Bookmark(1, 9, Bookmark {
+                position: Position::new(4.0, 2.0),
+                width: 12.0,
+                tracking: None,
+            }),
+        ];
+        for change in changes {
+            let written = encode_change(&change);
+            let read = decode_change(&written).unwrap();
+            assert_eq!(encode_change(&read), written);
+        }
+        assert!(decode_change("order 0 0 teleport").is_err());
+    }
+
+    #[test]
+    fn rules_read_back_as_written() {
+        let path = std::env::temp_dir().join(format!("sol-journal-rules-{}", std::process::id()));
+        let mut rules = Rules::new();
+        rules.light_lag = true;
+        rules.balance.set("birth_rate=0.125").unwrap();
+        JournalWriter::create(&path, "earths", rules).unwrap().flush();
+        let journal = Journal::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(journal.rules.light_lag);
+        assert_eq!(journal.rules.balance, rules.balance);
+        assert!(Journal::parse("scenario sol\nrules growth_boost=2\n").is_err());
+    }
+
+    #[test]
+    fn replay_ends_where_the_game_did() {
+        let path = std::env::temp_dir().join(format!("sol-journal-test-{}", std::process::id()));
+        let mut recorded = scenario::sol();
+        let mut tape = Tape::Record(JournalWriter::create(&path, "sol", Rules::new()).unwrap());
+        let ship = Ship::new(0, 1000, earth);
+        let steps = [
+            Change::Construct(Arc::new(ship.clone()), earth),
+            Change::Time(Duration::weeks(5)),
+            Change::Select(0, vec![0]),
+            Change::Order(0, Order::MoveTo(Position::new(2.0, 0.0)), false),
+            Change::Time(Duration::minutes(90)),
+            Change::Construct(Arc::new(ship), earth),
+            Change::Time(Duration::days(40)),
+        ];
+        for change in steps.iter() {
+            tape.apply(&mut recorded, change, &mut ModelAccess::write);
+        }
+        tape.flush();
+        let journal = Journal::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(journal.entries.len(), 4);
+
+        // the replay goes in different steps, it shouldn't matter
+        let mut replayed = journal.start_model().unwrap();
+        let mut tape = journal.tape();
+        // the screens can't change a replay
+        tape.apply(&mut replayed, &Change::Select(0, vec![]), &mut ModelAccess::write);
+        // 75 days and an hour and a half
+        for _ in 0..(75 * 48 + 3) {
+            tape.apply(&mut replayed, &Change::Time(Duration::minutes(30)), &mut ModelAccess::write);
+        }
+        let until = recorded.time + recorded.pending_time;
+        assert_eq!(replayed.time + replayed.pending_time, until);
+        assert_eq!(replayed.ships.len(), 2);
+        assert_eq!(replayed.players[0].money, recorded.players[0].money);
+        for (id, ship) in recorded.ships.iter() {
+            let position = |x: &Ship| x.movement.calc_position(&until, &recorded.galaxy);
+            assert_eq!(position(&replayed.ships[id]), position(ship));
+        }
+    }
+}
@@ -20,49 +20,59 @@
 use std::sync::{Arc, RwLock};
 use chrono::Duration;
 
-use crate::model::GameModel;
-use super::model_access::{ModelAccess, Change};
+use super::model_access::{Command, TickFlow};
 use super::thread_status::{ThreadControll, Status};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::SyncSender;
+use std::thread::JoinHandle;
+use std::{thread, time};
 
+// how much game time one step of the speed setting is
+type Granuality = Arc<RwLock<fn(i64)->Duration>>;
+
+// the clock, sends a time change every pace_ms while running
 pub struct Updater{
     pub controll:ThreadControll,
-    pub granuality:Arc<RwLock<fn(i64)->Duration>>,
-    pub change_queue:Option<Sender<Change>>,
-    pub model_writer:ModelAccess
+    pub granuality:Granuality,
 }
 
 impl Updater{
-    pub fn new(start_model:Arc<RwLock<GameModel>>, granuality:fn(i64)->Duration) -> Updater{
+    pub fn new(granuality:fn(i64)->Duration) -> Updater{
         let mut controll = ThreadControll::new();
         controll.set_status(Status::Paused);
         controll.set_pace(250);
         Updater{
             controll:controll,
             granuality:Arc::new(RwLock::new(granuality)),
-            model_writer:ModelAccess::new(start_model),
-            change_queue:None
         }
     }
-    pub fn start(&mut self) {
-        let sender = self.model_writer.start();
-        self.change_queue = Some(sender.clone());
-        let model = self.model_writer.game_model.clone();
+    pub fn start(&mut self, sender:SyncSender<Command>, flow:TickFlow) -> JoinHandle<()> {
         let granuality = self.granuality.clone();
+        let controll = self.controll.clone();
 
         self.controll.execute_logic(move ||{
-            Updater::update_nature(model.clone(), sender.clone(), granuality.clone());
+            Updater::update_nature(&sender, &granuality, &flow, &controll);
         })
     }
-    pub fn enqueue(&self, change:Change){
-        self.change_queue.clone().map(|x| x.send(change));
+    fn update_nature(sender:&SyncSender<Command>, granuality:&Granuality,
+                     flow:&TickFlow, controll:&ThreadControll){
+        // the last tick has to be applied first, even at speed 0 time
+        // can't get ahead of the writer
+        while flow.ticks_in_flight() > 0 {
+            if controll.get_status() != Status::Executing {
+                // paused or stopped while waiting, don't send another
+                return;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        let mktimefunc = granuality.read().expect("nobody panics holding the granuality");
+        flow.sending_tick();
+        // if the writer is gone the simulation reports why, nothing to do here
+        let _ = sender.send(Command::Tick(mktimefunc(1)));
     }
-    #[allow(unused_variables)] // need that lock
-    fn update_nature(model_writer:Arc<RwLock<GameModel>>, sender:Sender<Change>, granuality:Arc<RwLock<fn(i64)->Duration>>){
-        // obtain read lock to prevent going faster than the writer at speed 0
-        let lock = model_writer.read();
-        let mktimefunc = granuality.read().unwrap();
-        sender.send(Change::Time(mktimefunc(1))).expect("other side hung up");
+    // how much time one tick sends
+    pub fn step(&self) -> Duration{
+        let mktimefunc = self.granuality.read().expect("nobody panics holding the granuality");
+        mktimefunc(1)
     }
     pub fn set_granuality(&mut self, to:fn(i64)->Duration){
         *self.granuality.write().expect("writing new granu") = to;
@@ -27,129 +27,372 @@
 // It also should prevent cheating by doing model checks, such as, "is this
 // change even allowed". Because the clients may have been working on an older
 // state where things still could've worked.
+// Readers get the model as an immutable snapshot, after each batch of changes
+// the writer swaps in a new one. Nobody waits on a lock and a snapshot only
+// copies what changed since the last one.
 
 use std::thread;
-use std::sync::{Arc, RwLock, RwLockWriteGuard, RwLockReadGuard};
+use std::thread::JoinHandle;
+use std::sync::Arc;
 use chrono::Duration;
+use arc_swap::ArcSwap;
 
 use crate::model::{GameModel, PlayerID};
 use crate::model::galaxy::{BodyAddress,BodyClass};
 use crate::model::ship::ShipID;
+use crate::model::order::Order;
+use crate::model::bookmark::Bookmark;
 use crate::model::colony::*;
+use super::journal::Tape;
+use super::history::History;
 
-use crate::logic::thread_status::{ThreadControll, Status};
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::time::Instant;
+use std::iter;
 
 
+// what the writer thread gets send
+pub enum Command{
+    Apply(Change),
+    // time send by the clock, the only changes it waits for
+    Tick(Duration),
+    // the changes send before are still applied
+    Stop,
+}
+
+// how many changes may wait for the writer before whoever sends blocks
+const change_queue_size:usize = 64;
+
 #[derive(Clone)]
 pub struct ModelAccess{
-    pub game_model:Arc<RwLock<GameModel>>
+    // the last model the writer published, reading it never waits on the writer
+    snapshot:Arc<ArcSwap<GameModel>>,
+    pub flow:TickFlow,
+}
+
+// shared by the clock and the writer. the clock only sends the next time
+// change once the last one was applied, so time can't run ahead of the
+// writer and a pause stops it right away. it also measures how fast
+// simulated time actually goes.
+#[derive(Clone)]
+pub struct TickFlow{
+    // clock ticks send but not applied yet
+    in_flight:Arc<AtomicUsize>,
+    // simulated time applied so far
+    applied_ms:Arc<AtomicI64>,
+    meter:Arc<Mutex<RateMeter>>,
+}
+struct RateMeter{
+    measured_at:Instant,
+    applied_ms:i64,
+    sim_seconds_per_second:f64,
+}
+// measure over at least this long so the figure doesn't jump around
+const meter_window_ms:u64 = 1000;
+impl TickFlow{
+    pub fn new() -> TickFlow{
+        TickFlow{
+            in_flight:Arc::new(AtomicUsize::new(0)),
+            applied_ms:Arc::new(AtomicI64::new(0)),
+            meter:Arc::new(Mutex::new(RateMeter{
+                measured_at:Instant::now(),
+                applied_ms:0,
+                sim_seconds_per_second:0.0,
+            })),
+        }
+    }
+    pub fn ticks_in_flight(&self) -> usize{
+        self.in_flight.load(Ordering::SeqCst)
+    }
+    pub fn sending_tick(&self){
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+    fn applied(&self, ticks:usize, simulated:Duration){
+        self.applied_ms.fetch_add(simulated.num_milliseconds(), Ordering::SeqCst);
+        self.in_flight.fetch_sub(ticks, Ordering::SeqCst);
+    }
+    // simulated seconds per real second over the last window or so
+    pub fn sim_seconds_per_second(&self) -> f64{
+        let mut meter = self.meter.lock().expect("nobody panics holding the meter");
+        let elapsed = meter.measured_at.elapsed();
+        if elapsed >= std::time::Duration::from_millis(meter_window_ms) {
+            let applied = self.applied_ms.load(Ordering::SeqCst);
+            let real_ms = elapsed.as_secs() as f64 * 1000.0 + f64::from(elapsed.subsec_millis());
+            meter.sim_seconds_per_second = (applied - meter.applied_ms) as f64 / real_ms;
+            meter.applied_ms = applied;
+            meter.measured_at = Instant::now();
+        }
+        meter.sim_seconds_per_second
+    }
+}
+impl Default for TickFlow{
+    fn default() -> TickFlow{
+        TickFlow::new()
+    }
 }
 
 impl ModelAccess{
-    pub fn new(start_model:Arc<RwLock<GameModel>>) -> ModelAccess{
-        let mut controll = ThreadControll::new();
-        controll.set_status(Status::Aborted);
-        controll.set_pace(0);
+    pub fn new(start_model:GameModel) -> ModelAccess{
         ModelAccess{
-            game_model:start_model,
+            snapshot:Arc::new(ArcSwap::from_pointee(start_model)),
+            flow:TickFlow::new(),
         }
     }
-    pub fn start(&mut self) -> Sender<Change> {
+    // spawns the writer, it runs untill it's told to stop. with a tape the
+    // changes are journaled or come from a journal instead
+    pub fn start(&self, mut tape:Option<Tape>) -> (SyncSender<Command>, JoinHandle<()>) {
         println!("start access");
 
-        let game_model = self.game_model.clone();
-        let (sender,receiver) = channel();
+        let snapshot = self.snapshot.clone();
+        let flow = self.flow.clone();
+        let (sender,receiver) = sync_channel(change_queue_size);
 
-        thread::spawn(move ||{
+        let writer = thread::spawn(move ||{
+            let mut current = snapshot.load_full();
+            let mut history = History::new(current.clone());
             let mut running = true;
             while running{
-                match receiver.recv(){
-                    Ok(message) => ModelAccess::write(game_model.clone(), &message),
-                    _ => {
-                        running = false
+                let first = match receiver.recv(){
+                    Ok(command) => command,
+                    // everybody hung up
+                    _ => break
+                };
+                // readers may still hold the last snapshot, then we write in a
+                // copy. the systems in it are shared untill they change
+                let model = Arc::make_mut(&mut current);
+                let mut ticks = 0;
+                let mut simulated = Duration::zero();
+                // whatever came in meanwhile goes in the same snapshot
+                for command in iter::once(first).chain(receiver.try_iter()){
+                    let change = match command {
+                        Command::Apply(change) => change,
+                        Command::Tick(increase) => {
+                            ticks += 1;
+                            Change::Time(increase)
+                        }
+                        Command::Stop => {
+                            running = false;
+                            break;
+                        }
+                    };
+                    if let Change::Time(increase) = change {
+                        simulated = simulated + increase;
                     }
+                    match tape {
+                        Some(ref mut tape) => tape.apply(model, &change, &mut |model, change| history.write(model, change)),
+                        None => history.write(model, &change),
+                    }
+                }
+                if let Some(ref mut tape) = tape {
+                    tape.flush();
                 }
+                snapshot.store(current.clone());
+                // only now the clock may send more
+                flow.applied(ticks, simulated);
             }
         });
-        sender
+        (sender, writer)
     }
-    pub fn copy_model(&self) -> GameModel{
-        self.read_lock_model().clone()
+    // the model as last published, cheap to get and never changes
+    pub fn snapshot(&self) -> Arc<GameModel>{
+        self.snapshot.load_full()
     }
-    pub fn read_lock_model(&self) -> RwLockReadGuard<GameModel>{
-        if let Ok(gaurd) = self.game_model.read(){
-            return gaurd;
-        }
-        println!("poisned, try again");
-        self.read_lock_model()
+    // for when you want to change it, the systems are shared with the snapshot
+    pub fn copy_model(&self) -> GameModel{
+        (*self.snapshot()).clone()
     }
-    fn write(game_model:Arc<RwLock<GameModel>>, change:&Change){
+    // applies the change right away, without the writer thread, for whoever
+    // owns the model alone like the headless simulator
+    pub fn write(model:&mut GameModel, change:&Change){
         match *change{
 
             Change::Construct(ref constructable, address) =>{
-                let mut body = &mut game_model.write().expect("it").galaxy[address];
-                let class = body.class.clone();
-                body.class = if let BodyClass::Rocky(mut colony) = class {
+                // the work so far goes to what was queued before
+                model.settle_colony(address);
+                if let BodyClass::Rocky(ref mut colony) = model.galaxy[address].class {
                     colony.construction_queue.push(Construction::new(constructable.clone()));
-                    BodyClass::Rocky(colony)
-                }else{
-                    class
-                };
+                }
+                model.schedule_colony(address);
             }
 
-            Change::Time(increase) => ModelAccess::resource_tick(game_model.write().expect("it"), increase),
+            Change::Time(increase) => ModelAccess::advance(model, increase),
+
+            // only the history of the writer can go back
+            Change::Rewind(_) => {}
 
             Change::Select(player, ref selected) => {
-                game_model.write().expect("it").players[player].selected = selected.clone();
+                model.players[player].selected = selected.clone();
+            }
+
+            Change::FormFleet(player) => {
+                let selected = model.players[player].selected.clone();
+                model.form_fleet(player, &selected);
+            }
+
+            Change::MergeFleets(player) => {
+                let fleets = model.selected_fleets(player);
+                if let Some(into) = fleets.first(){
+                    model.merge_fleets(*into, &fleets);
+                }
+            }
+
+            Change::SplitFleet(player) => {
+                let selected = model.players[player].selected.clone();
+                model.split_fleet(player, &selected);
+            }
+
+            Change::Order(player, ref order, queued) => {
+                model.order_selection(player, order.clone(), queued);
+            }
+
+            Change::Bookmark(player, slot, bookmark) => {
+                model.set_bookmark(player, slot, bookmark);
             }
         }
     }
-    fn resource_tick(mut game_model:RwLockWriteGuard<GameModel>, interval:Duration){
-        game_model.time = game_model.time + interval;
-        let colony_unit = Colony::unit();
-        let changes:Vec<(BodyAddress,i64,Option<(usize,i64)>)> = game_model.galaxy.systems.iter()
-            .flat_map(|x| x.bodies.iter().filter_map(|cur| {
-                let colony = cur.get_colony().unwrap_or(&colony_unit);
-                if let Some(pop) = colony.population.clone(){
-                    Some((
-                        cur.address,
-                        pop.calc_head_increase(
-                            (colony.size*carrying_capacity_earth) as i64,
-                            interval
-                        ),
-                        // no owner, no tax change
-                        colony.owner.map_or(None, |x| Some((x,pop.calc_tax_over(interval) as i64))),
-                    ))
-                }else{None}
-            })
-            ).collect();
-        let mut constructions:Vec<(BodyAddress, AConstructable)> = Vec::new();
-        for change in changes{
-            let mut subject = game_model.galaxy[change.0].clone();
-            subject.class = if let BodyClass::Rocky(mut habitat) = subject.class.clone(){
-                change.2.map(|x| game_model.players[x.0].money += x.1);
-                habitat.population = habitat.population.map(|x| {
-                    x.change_headcount(change.1)  
-                });
-                constructions.append(
-                    &mut habitat.construction_tick(interval)
-                        .into_iter().map(|x| (subject.address, x)).collect()
-                );
-                BodyClass::Rocky(habitat)
-            }else{
-                subject.class
-            };
-            game_model.galaxy[change.0] = subject;
-        }
-        for construction in constructions{
-            construction.1.on_complete(&mut game_model, &construction.0);
+    // the simulation always moves in steps of simulation_step, whatever
+    // granularity was picked. what doesn't fill a step waits for the next
+    // time increase, so the same game time always gives the same game.
+    fn advance(game_model:&mut GameModel, increase:Duration){
+        game_model.pending_time = game_model.pending_time + increase;
+        while game_model.pending_time >= simulation_step() {
+            game_model.pending_time = game_model.pending_time - simulation_step();
+            ModelAccess::resource_tick(game_model, simulation_step());
         }
     }
+    fn resource_tick(game_model:&mut GameModel, interval:Duration){
+        let ship_positions = game_model.ship_positions();
+        game_model.time = game_model.time + interval;
+        game_model.deliver_orders();
+        // colonies and units that have something going on
+        game_model.run_schedule();
+        game_model.combat_tick(interval);
+        game_model.mine_tick(&ship_positions, interval);
+        game_model.missile_tick();
+        game_model.supply_tick();
+        game_model.sensor_tick();
+        game_model.survey_tick();
+    }
+}
+
+// one hour, small enough for orders to react and big enough for a week
+// to be a handful of steps
+pub fn simulation_step() -> Duration{
+    Duration::hours(1)
 }
 
+#[derive(Clone)]
 pub enum Change{
     Construct(AConstructable, BodyAddress),
     Select(PlayerID, Vec<ShipID>),
     Time(Duration),
+    // back to the game time, or as far back as the history goes
+    Rewind(Duration),
+    // these work on the player's current selection
+    FormFleet(PlayerID),
+    MergeFleets(PlayerID),
+    SplitFleet(PlayerID),
+    // queued means the order is added to the back of the queue
+    Order(PlayerID, Order, bool),
+    // the camera view under a number key
+    Bookmark(PlayerID, usize, Bookmark),
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use crate::logic::model_access::{Change, Command, ModelAccess};
+    use crate::model::galaxy::*;
+    use crate::model::scenario::testing::earth_model;
+    use crate::model::GameModel;
+
+    #[test]
+    fn granularity_does_not_change_the_game() {
+        let mut weekly = earth_model();
+        ModelAccess::advance(&mut weekly, Duration::weeks(1));
+        let mut daily = earth_model();
+        for _ in 0..7 {
+            ModelAccess::advance(&mut daily, Duration::days(1));
+        }
+        let mut by_minute = earth_model();
+        for _ in 0..(7 * 24 * 60) {
+            ModelAccess::advance(&mut by_minute, Duration::minutes(1));
+        }
+        let earth = BodyAddress {
+            system_id: 0,
+            planet_id: 1,
+        };
+        let head_count = |model: &GameModel| {
+            model.galaxy[earth]
+                .get_colony()
+                .and_then(|x| x.population.as_ref())
+                .map(|x| x.head_count)
+        };
+        for model in [&daily, &by_minute].iter() {
+            assert_eq!(model.time, weekly.time);
+            assert_eq!(model.players[0].money, weekly.players[0].money);
+            assert_eq!(head_count(model), head_count(&weekly));
+        }
+    }
+
+    #[test]
+    fn week_of_income_equals_seconds_of_income() {
+        let mut weekly = earth_model();
+        ModelAccess::resource_tick(&mut weekly, Duration::weeks(1));
+        let mut by_second = earth_model();
+        for _ in 0..604_800 {
+            ModelAccess::resource_tick(&mut by_second, Duration::seconds(1));
+            // paid every second instead of on payday
+            by_second.settle_all();
+        }
+        assert!(weekly.players[0].money > 0);
+        assert_eq!(weekly.players[0].money, by_second.players[0].money);
+        assert_eq!(
+            weekly.players[0].money_fraction,
+            by_second.players[0].money_fraction
+        );
+    }
+
+    #[test]
+    fn tick_is_published_before_the_next_may_go() {
+        let access = ModelAccess::new(earth_model());
+        let (sender, writer) = access.start(None);
+        access.flow.sending_tick();
+        sender
+            .send(Command::Tick(Duration::hours(3)))
+            .expect("writer is running");
+        let mut waited = 0;
+        while access.flow.ticks_in_flight() > 0 {
+            assert!(waited < 10_000, "writer never applied the tick");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            waited += 1;
+        }
+        // by the time the clock may send again it's visible to readers
+        assert_eq!(access.snapshot().time, Duration::hours(3));
+        sender.send(Command::Stop).expect("writer is running");
+        writer.join().expect("writer didn't panic");
+    }
+
+    #[test]
+    fn only_clock_ticks_are_waited_for() {
+        let access = ModelAccess::new(earth_model());
+        let (sender, writer) = access.start(None);
+        // time enqueued by others mixed with time from the clock
+        sender
+            .send(Command::Apply(Change::Time(Duration::hours(1))))
+            .expect("writer is running");
+        access.flow.sending_tick();
+        sender
+            .send(Command::Tick(Duration::hours(2)))
+            .expect("writer is running");
+        sender
+            .send(Command::Apply(Change::Time(Duration::hours(4))))
+            .expect("writer is running");
+        sender.send(Command::Stop).expect("writer is running");
+        writer.join().expect("writer didn't panic");
+        assert_eq!(access.flow.ticks_in_flight(), 0);
+        assert_eq!(access.snapshot().time, Duration::hours(7));
+    }
 }
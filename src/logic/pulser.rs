@@ -19,17 +19,20 @@
 // this is to prevent an issue with conrod which causes 100% usage on the
 // ui thread.
 
-use std::sync::{Arc, Mutex};
-use crate::state::state_machine::StateEvent;
+// it only raises a flag, what a pulse means is up to whoever takes it.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
 
 use super::thread_status::ThreadControll;
 
 pub struct Pulser{
-    pub event:Arc<Mutex<StateEvent>>,
+    pub pulsed:Arc<AtomicBool>,
     pub controll:ThreadControll
 }
 impl Pulser{
-    pub fn new(start_event:StateEvent) -> Pulser{
+    pub fn new() -> Pulser{
         let mut controll = ThreadControll::new();
         // about 50~60 is the minimum witout cpu buildup from conrod,
         // we keep a safe 100ms, this runs on a seperate thread from the
@@ -38,20 +41,23 @@ impl Pulser{
         // see https://github.com/PistonDevelopers/conrod/issues/814
         controll.set_pace(16);
         Pulser{
-            event:Arc::new(Mutex::new(start_event)),
+            pulsed:Arc::new(AtomicBool::new(false)),
             controll:controll
         }
     }
-    pub fn get_event(&self) -> StateEvent{
-        let mut shared = self.event.lock().expect("poisining on read event");
-        let result = *shared;
-        *shared = StateEvent::Idle;
-        result
+    // true once after each pulse
+    pub fn take_pulse(&self) -> bool{
+        self.pulsed.swap(false, Ordering::SeqCst)
     }
-    pub fn start(&self) {
-        let event = self.event.clone();
+    pub fn start(&self) -> JoinHandle<()> {
+        let pulsed = self.pulsed.clone();
         self.controll.execute_logic(move ||{
-            *event.lock().expect("posining on set event") = StateEvent::WantsUpdate;
-        });
+            pulsed.store(true, Ordering::SeqCst);
+        })
+    }
+}
+impl Default for Pulser{
+    fn default() -> Pulser{
+        Pulser::new()
     }
 }
@@ -0,0 +1,190 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the simulation service. there's one per game, it owns
+// the writer, clock, pulser and forecast threads and hands out cheap handles to send
+// changes trough. on shutdown every thread is stopped and joined, a thread
+// that panicked is reported with what it said instead of leaving the
+// others waiting on it.
+
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::mpsc::SyncSender;
+use std::thread::JoinHandle;
+use chrono::Duration;
+use arc_swap::ArcSwapOption;
+
+use crate::model::GameModel;
+use super::model_access::{Change, Command, ModelAccess};
+use super::logic_updater::Updater;
+use super::pulser::Pulser;
+use super::journal::Tape;
+use super::forecast::{Forecast, Forecaster};
+
+// how far ahead the planet screen looks
+const forecast_months:i64 = 12;
+
+// what the screens get to read and change the game
+#[derive(Clone)]
+pub struct SimulationHandle{
+    sender:SyncSender<Command>,
+    model:ModelAccess,
+    forecast:Arc<ArcSwapOption<Forecast>>,
+}
+impl SimulationHandle{
+    pub fn enqueue(&self, change:Change){
+        // if the writer is gone the simulation reports why
+        let _ = self.sender.send(Command::Apply(change));
+    }
+    pub fn snapshot(&self) -> Arc<GameModel>{
+        self.model.snapshot()
+    }
+    pub fn copy_model(&self) -> GameModel{
+        self.model.copy_model()
+    }
+    // the last forecast that's done, it may be from an older snapshot
+    pub fn forecast(&self) -> Option<Arc<Forecast>>{
+        self.forecast.load_full()
+    }
+}
+
+pub struct Simulation{
+    handle:SimulationHandle,
+    pub clock:Updater,
+    pub pulser:Pulser,
+    pub forecaster:Forecaster,
+    threads:Vec<(&'static str, JoinHandle<()>)>,
+    // threads that went down, with what they said
+    failures:Vec<String>,
+}
+impl Simulation{
+    // starts all threads, the clock starts paused
+    pub fn start(start_model:GameModel, granuality:fn(i64)->Duration, tape:Option<Tape>) -> Simulation{
+        let model = ModelAccess::new(start_model);
+        let (sender, writer) = model.start(tape);
+        let mut clock = Updater::new(granuality);
+        let clock_thread = clock.start(sender.clone(), model.flow.clone());
+        let pulser = Pulser::new();
+        let pulser_thread = pulser.start();
+        let forecaster = Forecaster::new(forecast_months);
+        let forecast_thread = forecaster.start(model.clone());
+        Simulation{
+            handle:SimulationHandle{
+                sender,
+                model,
+                forecast:forecaster.latest.clone(),
+            },
+            clock,
+            pulser,
+            forecaster,
+            threads:vec![
+                ("writer", writer),
+                ("clock", clock_thread),
+                ("pulser", pulser_thread),
+                ("forecast", forecast_thread),
+            ],
+            failures:Vec::new(),
+        }
+    }
+    pub fn handle(&self) -> SimulationHandle{
+        self.handle.clone()
+    }
+    pub fn enqueue(&self, change:Change){
+        self.handle.enqueue(change);
+    }
+    pub fn snapshot(&self) -> Arc<GameModel>{
+        self.handle.snapshot()
+    }
+    // simulated seconds per real second, for the hud
+    pub fn sim_seconds_per_second(&self) -> f64{
+        self.handle.model.flow.sim_seconds_per_second()
+    }
+    // none of the threads should stop before shutdown, the ones that did are
+    // joined and reported here
+    pub fn failures(&mut self) -> &[String]{
+        let (stopped, running):(Vec<_>, Vec<_>) = self.threads.drain(..).partition(|x| x.1.is_finished());
+        self.threads = running;
+        for (name, thread) in stopped{
+            let failure = match thread.join() {
+                Ok(()) => format!("{} thread stopped", name),
+                Err(panic) => format!("{} thread panicked: {}", name, panic_message(&panic)),
+            };
+            println!("{}", failure);
+            self.failures.push(failure);
+        }
+        &self.failures
+    }
+    // stops and joins every thread, changes send before are still applied
+    pub fn shutdown(&mut self) -> Result<(), Vec<String>>{
+        self.clock.stop();
+        self.pulser.controll.stop();
+        self.forecaster.controll.stop();
+        // it may be gone already
+        let _ = self.handle.sender.send(Command::Stop);
+        for (name, thread) in self.threads.drain(..){
+            if let Err(panic) = thread.join() {
+                self.failures.push(format!("{} thread panicked: {}", name, panic_message(&panic)));
+            }
+        }
+        if self.failures.is_empty() {
+            Ok(())
+        }else{
+            Err(self.failures.clone())
+        }
+    }
+}
+impl Drop for Simulation{
+    fn drop(&mut self){
+        if self.threads.is_empty() {
+            return;
+        }
+        if let Err(failures) = self.shutdown() {
+            for failure in failures{
+                println!("{}", failure);
+            }
+        }
+    }
+}
+
+fn panic_message(panic:&Box<dyn Any + Send>) -> String{
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = panic.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "no message".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::model_access::Change;
+    use crate::logic::simulation::Simulation;
+    use crate::model::scenario::testing::sun_model;
+    use chrono::Duration;
+
+    #[test]
+    fn shutdown_applies_what_was_send_and_joins() {
+        let mut simulation = Simulation::start(sun_model(), Duration::days, None);
+        let handle = simulation.handle();
+        handle.enqueue(Change::Time(Duration::days(2)));
+        assert!(simulation.failures().is_empty());
+        assert!(simulation.shutdown().is_ok());
+        // the clock was paused, only our change moved time
+        assert_eq!(handle.snapshot().time, Duration::days(2));
+    }
+}
@@ -24,6 +24,7 @@
 use std::time;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::thread::JoinHandle;
 
 fn sleep(){
     let one_ms = time::Duration::from_millis(1);
@@ -62,14 +63,15 @@ impl ThreadControll{
             status:Arc::new(RwLock::new(ThreadStatus::new()))
         }
     }
-    pub fn execute_logic<F, T>(&self, threadlogic:F) -> ()
+    // runs the logic on a new thread in whatever status we're in now, so a
+    // paused controll starts paused. join the handle after stopping it
+    pub fn execute_logic<F, T>(&self, threadlogic:F) -> JoinHandle<()>
         where F: Fn() -> T, F: Send + 'static, T: Send + 'static
     {
-        self.status.write().expect("running").status = Status::Executing;
         let controll = self.status.clone();
         thread::spawn(move|| {
             with_default_controlls(controll, threadlogic); 
-        });
+        })
     }
     pub fn toggle_pause(&mut self){
         let newstatus = match self.status.read().expect("poisen").status {
@@ -28,13 +28,7 @@ use input::Loop::*;
 use piston_window::Event::*;
 use conrod::image::Map;
 
-pub mod camera;
-pub mod geometry;
-pub mod logic;
-pub mod model;
-pub mod state;
-pub mod view;
-
+use sol::state;
 use state::begin::BeginState;
 use state::state_machine::StateMachine;
 
@@ -61,7 +55,8 @@ fn main() {
         0,
         WindowSettings::new(format!("{} - {}", NAME, VERSION), [WIDTH, HEIGHT])
             .samples(4)
-            .exit_on_esc(true)
+            // the state gets to close what it has open first
+            .exit_on_esc(false)
             .srgb(false)
             .vsync(true)
             .build()
@@ -140,6 +135,16 @@ fn main() {
                 }
             },
             Input(i) => {
+                if let input::Input::Button(input::ButtonArgs {
+                    state: input::ButtonState::Press,
+                    button: input::Button::Keyboard(input::keyboard::Key::Escape),
+                    ..
+                }) = i
+                {
+                    if !state_machine.escape() {
+                        window.set_should_close(true);
+                    }
+                }
                 state_machine.input(i);
                 should_update = true
             }
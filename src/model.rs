@@ -16,13 +16,40 @@
 
 
 // This is the root model or model abstract, the root of the state of the program
+pub mod accounting;
 pub mod galaxy;
 pub mod colony;
+pub mod demography;
 pub mod ship;
+pub mod fleet;
+pub mod order;
+pub mod sensor;
+pub mod light_lag;
+pub mod survey;
+pub mod invasion;
+pub mod station;
+pub mod combat;
+pub mod spatial;
+pub mod mines;
+pub mod missile;
+pub mod schedule;
+pub mod scenario;
+pub mod bookmark;
 
 use chrono::Duration;
 use galaxy::{System, BodyAddress, Galaxy};
 use ship::{Ship, ShipID};
+use fleet::{Fleet, FleetID};
+use sensor::{Contact, ContactID};
+use light_lag::PendingOrder;
+use accounting::Amount;
+use station::{Station, StationID};
+use mines::{Minefield, MinefieldID};
+use missile::{Missile, MissileID};
+use schedule::Schedule;
+use demography::Balance;
+use bookmark::Bookmark;
+use std::collections::{BTreeMap, BTreeSet};
 use std::usize;
 
 // top level datastructure, all other models should be attached to this.
@@ -32,8 +59,23 @@ use std::usize;
 pub struct GameModel{
     pub galaxy:Galaxy,
     pub players:Vec<Player>,
-    pub ships:Vec<Ship>,
-    pub time:Duration
+    pub ships:BTreeMap<ShipID, Ship>,
+    pub next_ship_id:ShipID,
+    pub fleets:BTreeMap<FleetID, Fleet>,
+    pub next_fleet_id:FleetID,
+    pub stations:BTreeMap<StationID, Station>,
+    pub next_station_id:StationID,
+    pub minefields:BTreeMap<MinefieldID, Minefield>,
+    pub next_minefield_id:MinefieldID,
+    pub missiles:BTreeMap<MissileID, Missile>,
+    pub next_missile_id:MissileID,
+    pub pending_orders:Vec<PendingOrder>,
+    // what's going to happen when, so idle things cost nothing
+    pub schedule:Schedule,
+    pub rules:Rules,
+    pub time:Duration,
+    // time asked for that didn't fill a simulation step yet
+    pub pending_time:Duration
 }
 impl GameModel{
     pub fn new(systems:Vec<System>) -> GameModel{
@@ -47,16 +89,63 @@ impl GameModel{
             newsys.bodies = newbodies;
             newsys
         }).collect();
-        GameModel{
-            galaxy:Galaxy::new(addressed),
-            players:vec![Player{
-                money:0,
-                id:0,
-                selected:Vec::new()
-            }],
-            ships:Vec::new(),
-            time:Duration::zero()
-        } 
+        let galaxy = Galaxy::new(addressed);
+        // a player for every owner, and one at the screen if nobody owns anything
+        let last_owner = galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter_map(|x| x.get_colony().and_then(|colony| colony.owner))
+            .max().unwrap_or(0);
+        let mut result = GameModel{
+            galaxy,
+            players:(0..=last_owner).map(Player::new).collect(),
+            ships:BTreeMap::new(),
+            next_ship_id:0,
+            fleets:BTreeMap::new(),
+            next_fleet_id:0,
+            stations:BTreeMap::new(),
+            next_station_id:0,
+            minefields:BTreeMap::new(),
+            next_minefield_id:0,
+            missiles:BTreeMap::new(),
+            next_missile_id:0,
+            pending_orders:Vec::new(),
+            schedule:Schedule::new(),
+            rules:Rules::new(),
+            time:Duration::zero(),
+            pending_time:Duration::zero()
+        };
+        // the first paydays
+        result.settle_all();
+        // you know your own home
+        result.survey_tick();
+        result
+    }
+    // the model at a time between two steps, only for drawing. bodies and
+    // ships move in closed form so they're exact, missiles wait for the step
+    pub fn interpolated(mut self) -> GameModel{
+        self.time = self.time + self.pending_time;
+        self.pending_time = Duration::zero();
+        self
+    }
+}
+
+// optional game rules, picked before the game starts
+#[derive(Clone, Copy)]
+pub struct Rules{
+    // sensor images and orders travel at the speed of light
+    pub light_lag:bool,
+    pub balance:Balance,
+}
+impl Rules{
+    pub fn new() -> Rules{
+        Rules{
+            light_lag:false,
+            balance:Balance::new(),
+        }
+    }
+}
+impl Default for Rules{
+    fn default() -> Rules{
+        Rules::new()
     }
 }
 
@@ -64,6 +153,30 @@ pub type PlayerID = usize;
 #[derive(Clone)]
 pub struct Player{
     pub money:i64,
+    // income that doesn't make a whole coin yet
+    pub money_fraction:Amount,
     pub id:PlayerID,
-    pub selected:Vec<ShipID>
+    pub selected:Vec<ShipID>,
+    pub contacts:BTreeMap<ContactID, Contact>,
+    // bodies of which we know what's on them
+    pub surveyed:BTreeSet<BodyAddress>,
+    // camera views under the number keys
+    pub bookmarks:BTreeMap<usize, Bookmark>,
+}
+impl Player{
+    pub fn new(id:PlayerID) -> Player{
+        Player{
+            money:0,
+            money_fraction:Amount::zero(),
+            id,
+            selected:Vec::new(),
+            contacts:BTreeMap::new(),
+            surveyed:BTreeSet::new(),
+            bookmarks:BTreeMap::new(),
+        }
+    }
+    pub fn earn(&mut self, amount:Amount){
+        self.money_fraction += amount;
+        self.money += self.money_fraction.take_whole();
+    }
 }
@@ -0,0 +1,143 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains fixed point amounts for money and people. a rate is
+// turned into parts per millisecond once, after that everything is integer
+// math, so a week at once adds up to exactly the same as a week of seconds.
+// what doesn't make a whole coin or person yet is kept for later.
+
+use std::ops::{Add, AddAssign, Neg};
+use chrono::Duration;
+
+// parts in a whole, fine enough that rounding a rate to whole parts per
+// millisecond doesn't matter
+const scale:i128 = 1_000_000_000_000_000;
+const millis_per_day:f64 = 86_400_000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Amount(i128);
+impl Amount{
+    pub fn zero() -> Amount{
+        Amount(0)
+    }
+    pub fn whole(count:i64) -> Amount{
+        Amount(count as i128 * scale)
+    }
+    pub fn from_f64(amount:f64) -> Amount{
+        Amount((amount * scale as f64).round() as i128)
+    }
+    pub fn to_f64(&self) -> f64{
+        self.0 as f64 / scale as f64
+    }
+    // moves the whole units out, the fraction stays behind
+    pub fn take_whole(&mut self) -> i64{
+        let whole = self.0.div_euclid(scale);
+        self.0 = self.0.rem_euclid(scale);
+        whole as i64
+    }
+}
+impl Add for Amount{
+    type Output = Amount;
+    fn add(self, other:Amount) -> Amount{
+        Amount(self.0 + other.0)
+    }
+}
+impl AddAssign for Amount{
+    fn add_assign(&mut self, other:Amount){
+        self.0 += other.0;
+    }
+}
+impl Neg for Amount{
+    type Output = Amount;
+    fn neg(self) -> Amount{
+        Amount(-self.0)
+    }
+}
+
+// parts per millisecond
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Rate(i128);
+impl Rate{
+    pub fn zero() -> Rate{
+        Rate(0)
+    }
+    pub fn per_day(amount:f64) -> Rate{
+        Rate((amount * scale as f64 / millis_per_day).round() as i128)
+    }
+    pub fn per_duration(amount:f64, duration:Duration) -> Rate{
+        Rate((amount * scale as f64 / duration.num_milliseconds() as f64).round() as i128)
+    }
+    pub fn over(&self, duration:Duration) -> Amount{
+        Amount(self.0 * duration.num_milliseconds() as i128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use crate::model::accounting::*;
+    use proptest::prelude::*;
+
+    // a couple of awkward rates, the property below tries many more
+    const rates: [f64; 5] = [0.000_001, 0.3, 17.25, 1_157_407.407, -42.5];
+
+    #[test]
+    fn week_equals_seconds() {
+        for per_day in rates.iter() {
+            let rate = Rate::per_day(*per_day);
+            let at_once = rate.over(Duration::weeks(1));
+            let mut by_second = Amount::zero();
+            for _ in 0..604_800 {
+                by_second += rate.over(Duration::seconds(1));
+            }
+            assert_eq!(at_once, by_second);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pieces_add_up_to_the_whole(
+            per_day in -1e9f64..1e9,
+            pieces in prop::collection::vec(0i64..1_000_000_000, 1..100)
+        ) {
+            let rate = Rate::per_day(per_day);
+            let mut by_piece = Amount::zero();
+            for millis in pieces.iter() {
+                by_piece += rate.over(Duration::milliseconds(*millis));
+            }
+            let at_once = rate.over(Duration::milliseconds(pieces.iter().sum()));
+            prop_assert_eq!(at_once, by_piece);
+        }
+    }
+
+    #[test]
+    fn fractions_are_kept() {
+        // a whole number of parts per millisecond
+        let rate = Rate::per_day(0.864);
+        let mut pocket = Amount::zero();
+        let mut taken = 0;
+        for _ in 0..10 {
+            pocket += rate.over(Duration::days(1));
+            taken += pocket.take_whole();
+        }
+        assert_eq!(taken, 8);
+        assert!((pocket.to_f64() - 0.64).abs() < 1e-12);
+        let mut debt = -Amount::whole(1) + Rate::per_day(0.216).over(Duration::days(1));
+        assert_eq!(debt.take_whole(), -1);
+        assert!((debt.to_f64() - 0.216).abs() < 1e-12);
+    }
+}
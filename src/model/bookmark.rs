@@ -0,0 +1,146 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models where the players look. what the camera follows and the
+// bookmarks are kept in the game, so they're journaled and replayed with it.
+// the go to search finds things by name, in a generated galaxy there's too
+// much to find by scrolling around.
+
+use crate::geometry::Position;
+use super::galaxy::{Au, BodyAddress, BodyClass};
+use super::fleet::FleetID;
+use super::ship::ShipID;
+use super::{GameModel, PlayerID};
+
+// bookmarks are under the number keys
+pub const bookmark_slots:usize = 10;
+// close enough to see a formation
+const ship_view_width:Au = 0.01;
+// the whole system and a bit of space around it
+const system_view_margin:f64 = 2.5;
+
+// what the camera can follow around
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tracking{
+    Body(BodyAddress),
+    Ship(ShipID),
+    Fleet(FleetID),
+    // the star in the middle
+    System(usize),
+}
+impl Tracking{
+    // None once it's gone, ships get destroyed and fleets split up
+    pub fn position(&self, model:&GameModel) -> Option<Position>{
+        let galaxy = &model.galaxy;
+        match *self {
+            Tracking::Body(address) => Some(galaxy[address].calc_position(&model.time)),
+            Tracking::Ship(id) => model.ships.get(&id).map(|x| x.movement.calc_position(&model.time, galaxy)),
+            Tracking::Fleet(id) => model.fleets.get(&id).and_then(|x| model.ships.get(&x.flagship))
+                .map(|x| x.movement.calc_position(&model.time, galaxy)),
+            Tracking::System(id) => galaxy.systems.get(id).map(|x| x.used_space.position),
+        }
+    }
+    pub fn name(&self, model:&GameModel) -> String{
+        match *self {
+            Tracking::Body(address) => model.galaxy[address].name.to_string(),
+            Tracking::Ship(id) => format!("ship {}", id),
+            Tracking::Fleet(id) => model.fleets.get(&id).map_or(format!("fleet {}", id), |x| x.name.clone()),
+            Tracking::System(id) => format!("system {}", id),
+        }
+    }
+    // how wide the view should be to see it with what's around it
+    pub fn view_width(&self, model:&GameModel) -> Au{
+        let system = |id:usize| model.galaxy.systems.get(id)
+            .map_or(ship_view_width, |x| x.used_space.radius * system_view_margin);
+        match *self {
+            Tracking::Body(address) => match model.galaxy[address].class {
+                BodyClass::Star => system(address.system_id),
+                // its neighbours are about this far away too
+                _ => (model.galaxy[address].distance / 2.0).max(ship_view_width),
+            },
+            Tracking::Ship(_) | Tracking::Fleet(_) => ship_view_width,
+            Tracking::System(id) => system(id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Bookmark{
+    pub position:Position,
+    pub width:Au,
+    // followed again when the bookmark is picked
+    pub tracking:Option<Tracking>,
+}
+
+impl GameModel{
+    pub fn set_bookmark(&mut self, player:PlayerID, slot:usize, bookmark:Bookmark){
+        if slot < bookmark_slots {
+            self.players[player].bookmarks.insert(slot, bookmark);
+        }
+    }
+    // everything the player can go to whose name has all the words in it,
+    // bodies first, then systems, ships and fleets
+    pub fn destinations(&self, player:PlayerID, query:&str) -> Vec<(String, Tracking)>{
+        let query = query.to_lowercase();
+        let matches = |label:&str| {
+            let label = label.to_lowercase();
+            query.split_whitespace().all(|x| label.contains(x))
+        };
+        let bodies = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter()).map(|body| {
+            let label = match body.get_colony().and_then(|x| x.owner) {
+                Some(owner) if owner == player => format!("{}, our colony", body.name),
+                Some(owner) => format!("{}, colony of player {}", body.name, owner),
+                None => body.name.to_string(),
+            };
+            (label, Tracking::Body(body.address))
+        });
+        let systems = (0..self.galaxy.systems.len()).map(Tracking::System);
+        let ships = self.ships.keys().map(|x| Tracking::Ship(*x));
+        let fleets = self.fleets.keys().map(|x| Tracking::Fleet(*x));
+        bodies.chain(systems.chain(ships).chain(fleets).map(|x| (x.name(self), x)))
+            .filter(|x| matches(&x.0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::bookmark::Tracking;
+    use crate::model::galaxy::BodyAddress;
+    use crate::model::scenario;
+
+    #[test]
+    fn destinations_are_found_by_name() {
+        let earth = BodyAddress {
+            system_id: 0,
+            planet_id: 3,
+        };
+        let model = scenario::sol();
+        let found = model.destinations(0, "EARTH");
+        assert_eq!(found, vec![("earth, our colony".to_owned(), Tracking::Body(earth))]);
+        assert_eq!(model.destinations(1, "colony earth")[0].0, "earth, colony of player 0");
+        // the sun, eight planets and the system
+        assert_eq!(model.destinations(0, "").len(), 10);
+        assert!(model.destinations(0, "pluto").is_empty());
+        let sun = Tracking::Body(BodyAddress {
+            system_id: 0,
+            planet_id: 0,
+        });
+        assert!(sun.view_width(&model) > 60.0);
+        assert!(Tracking::Body(earth).view_width(&model) < 1.0);
+    }
+}
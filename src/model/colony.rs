@@ -24,6 +24,10 @@
 use chrono::Duration;
 use super::galaxy::{Earths,BodyAddress };
 use super::{GameModel};
+use super::invasion::{GroundBattle, garrison_for};
+use super::schedule::PayPeriod;
+use super::accounting::{Amount, Rate};
+use super::demography::{Balance, Cohorts, logistic_change, logistic_integral};
 use std::usize;
 use std::sync::Arc;
 
@@ -34,6 +38,18 @@ pub struct Colony{
     // not colonized, no pop
     pub population:Option<Population>,
     pub construction_queue:Vec<Construction>,
+    // 0 is a dead rock, 1 is earth
+    pub habitability:f64,
+    pub deposits:Deposits,
+    // soldiers defending the colony
+    pub garrison:f64,
+    pub battle:Option<GroundBattle>,
+    // the model time the colony was last brought up to date
+    pub settled_at:Duration,
+    // what the people since are worked out from
+    pub pay_period:Option<PayPeriod>,
+    // when it's scheduled to be brought up to date again
+    pub due:Option<Duration>,
 }
 impl Colony{
     pub fn unit()->Colony{
@@ -44,11 +60,24 @@ impl Colony{
             population:None,
             owner:None,
             size:size,
-            construction_queue:Vec::new()
+            construction_queue:Vec::new(),
+            habitability:0.0,
+            deposits:Deposits::none(),
+            garrison:0.0,
+            battle:None,
+            settled_at:Duration::zero(),
+            pay_period:None,
+            due:None,
         }
     }
+    pub fn with_resources(mut self, habitability:f64, deposits:Deposits) -> Colony{
+        self.habitability = habitability;
+        self.deposits = deposits;
+        self
+    }
     pub fn new_inhabited(owner:usize, surface:Earths, population:Population)->Colony{
         let mut result = Colony::new_empty(surface);
+        result.garrison = garrison_for(population.head_count);
         result.population = Some(population);
         result.owner = Some(owner);
         result
@@ -65,7 +94,6 @@ impl Colony{
                 result.push(job.constructable);
                 result
             }else{
-                println!("not finished ship");
                 self.construction_queue.push(job);
                 not_done_yet
             },
@@ -74,6 +102,26 @@ impl Colony{
     }
 }
 
+// how rich the ground is, 1 is about what earth had
+#[derive(Clone, Copy, PartialEq)]
+pub struct Deposits{
+    pub metal:f64,
+    pub rare_earth:f64,
+    pub fuel:f64,
+}
+impl Deposits{
+    pub fn none() -> Deposits{
+        Deposits::new(0.0, 0.0, 0.0)
+    }
+    pub fn new(metal:f64, rare_earth:f64, fuel:f64) -> Deposits{
+        Deposits{
+            metal,
+            rare_earth,
+            fuel,
+        }
+    }
+}
+
 pub type AConstructable = Arc<dyn Constructable + Send + Sync>;
 #[derive(Clone)]
 pub struct Construction{
@@ -91,60 +139,136 @@ impl Construction{
     pub fn work_on(&mut self, time_passed:Duration) -> Option<Duration>{
         self.progress = self.progress + time_passed;
         let needed = self.constructable.work_needed();
-        if needed < self.progress {
+        if needed <= self.progress {
             Some(self.progress - needed)
         }else{
             None
         }
     }
+    pub fn remaining(&self) -> Duration{
+        self.constructable.work_needed() - self.progress
+    }
 }
 #[allow(unused_variables)]
 pub trait Constructable{
     fn on_complete(&self, model:&mut GameModel, contructor_address:&BodyAddress)->(){}
     fn work_needed(&self) -> Duration{Duration::weeks(4)}
     fn price(&self) -> i64;
+    // how it's written in the journal, read back by journal::decode_change
+    fn journal(&self) -> String;
 }
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Population{
     pub head_count:i64,
-    pub tax:f64 // annual tax pp
+    pub tax:f64, // annual tax pp
+    // 1 is fully loyal to the owner, a conquered people starts low
+    pub loyalty:f64,
+    // riots and strikes, 0 is calm
+    pub unrest:f64,
+    // people on the way that don't make a whole person yet
+    pub head_fraction:Amount,
+    pub cohorts:Cohorts,
 }
 impl Population{
     pub fn new(head_count:i64)->Population{
         Population{
             head_count:head_count,
             tax:0.1,
+            loyalty:1.0,
+            unrest:0.0,
+            head_fraction:Amount::zero(),
+            cohorts:Cohorts::stable(),
         } 
     }
+    // fresh settlers, all adults
+    pub fn colonists(head_count:i64)->Population{
+        Population{
+            cohorts:Cohorts::colonists(),
+            ..Population::new(head_count)
+        }
+    }
     pub fn change_headcount(mut self, by:i64) -> Self{
         self.head_count += by;
         self
     }
+    // like change_headcount but keeps the fraction for next time
+    pub fn grow(mut self, by:Amount) -> Self{
+        self.head_fraction += by;
+        self.head_count += self.head_fraction.take_whole();
+        self
+    }
+    // unrest dies down fast, loyalty takes a generation to win back
+    pub fn calm_down(mut self, duration:Duration) -> Self{
+        let weeks = duration.num_milliseconds() as f64 / Duration::weeks(1).num_milliseconds() as f64;
+        self.unrest *= (0.5f64).powf(weeks / unrest_half_life_weeks);
+        self.loyalty = 1.0 - (1.0 - self.loyalty) * (0.5f64).powf(weeks / loyalty_half_life_weeks);
+        self
+    }
+    pub fn conquered(mut self) -> Self{
+        self.loyalty = conquered_loyalty;
+        self.unrest = 1.0;
+        self
+    }
+    // the fraction of tax that actually gets paid
+    pub fn compliance(&self) -> f64{
+        self.loyalty * (1.0 - self.unrest)
+    }
+    pub fn tax_rate(&self) -> Rate{
+        Rate::per_day(self.tax * (self.head_count as f64) * self.compliance())
+    }
     pub fn calc_tax_over(&self, duration:Duration) -> f64{
-        let fraction = duration.num_milliseconds() as f64 / Duration::days(1).num_milliseconds() as f64;
-        self.tax * (self.head_count as f64) * fraction
+        self.tax_rate().over(duration).to_f64()
+    }
+    // the fraction of tax paid on average while unrest and loyalty recover
+    // for the duration, both decay exponentially so it's solved exactly
+    pub fn mean_compliance(&self, duration:Duration) -> f64{
+        let weeks = duration.num_milliseconds() as f64 / Duration::weeks(1).num_milliseconds() as f64;
+        let disloyalty = 1.0 - self.loyalty;
+        let both = 1.0 / (1.0 / unrest_half_life_weeks + 1.0 / loyalty_half_life_weeks);
+        // (1 - disloyalty * decay) * (1 - unrest * decay) multiplied out
+        1.0 - disloyalty * mean_decay(loyalty_half_life_weeks, weeks)
+            - self.unrest * mean_decay(unrest_half_life_weeks, weeks)
+            + disloyalty * self.unrest * mean_decay(both, weeks)
+    }
+    // tax paid while the population follows its curve for the duration.
+    // people and compliance are averaged apart, neither changes much in
+    // between paydays
+    pub fn calc_tax_growing(&self, carrying_capacity:i64, duration:Duration, balance:&Balance) -> Amount{
+        let head_count = self.exact_head_count();
+        let rate = self.cohorts.growth_rate(head_count, balance);
+        let person_days = logistic_integral(head_count, carrying_capacity as f64, rate, duration) * 365.0;
+        Amount::from_f64(self.tax * person_days * self.mean_compliance(duration))
+    }
+    fn exact_head_count(&self) -> f64{
+        self.head_count as f64 + self.head_fraction.to_f64()
+    }
+    // people born minus people died over the duration
+    pub fn calc_growth(&self, carrying_capacity:i64, duration:Duration, balance:&Balance) -> Amount{
+        let head_count = self.exact_head_count();
+        let rate = self.cohorts.growth_rate(head_count, balance);
+        Amount::from_f64(logistic_change(head_count, carrying_capacity as f64, rate, duration))
     }
+    // with the usual balance
     pub fn calc_head_increase(&self, carrying_capacity:i64, duration:Duration) -> i64{
-        // lets say we reach carrying capacity in 50 years
-        let cc_fraction = (self.head_count as f64) / (carrying_capacity as f64);
-
-        if cc_fraction > 1.0 {
-            let week = Duration::weeks(1).num_milliseconds() as f64;
-            let death_time = (duration.num_milliseconds() as f64)/week;
-            let deaths_fraction = ((cc_fraction-1.0)*death_fraction_per_week) * death_time;
-            return -(deaths_fraction * (self.head_count as f64)) as i64;
-        }
-        let thirthy_year = Duration::weeks(52*30).num_milliseconds() as f64;
-        let time_fraction = duration.num_milliseconds() as f64 / thirthy_year;
-        let fertile_pop = (self.head_count as f64) * fertile_female_fraction;
-        //TODO: limit growth for small population sizes
-        // ie, figure female count, times amount of babies they get over duration
-        // only neccesary for postive growth
-        (time_fraction * fertile_pop * ((1.0-cc_fraction)*growth_boost)) as i64
+        self.calc_growth(carrying_capacity, duration, &Balance::new()).to_f64() as i64
+    }
+    pub fn age(mut self, duration:Duration, balance:&Balance) -> Self{
+        self.cohorts = self.cohorts.age(self.exact_head_count(), duration, balance);
+        self
     }
 }
 pub const carrying_capacity_earth:f64 = 10000000000.0;
-const fertile_female_fraction:f64 = 0.5*0.3;
-const growth_boost:f64 = 1.0;
 
-const death_fraction_per_week:f64 = 0.1;
+
+const unrest_half_life_weeks:f64 = 8.0;
+const loyalty_half_life_weeks:f64 = 52.0 * 5.0;
+const conquered_loyalty:f64 = 0.2;
+
+// the average of something halving every half life over the weeks
+fn mean_decay(half_life_weeks:f64, weeks:f64) -> f64{
+    if weeks <= 0.0 {
+        return 1.0;
+    }
+    let halvings = weeks / half_life_weeks;
+    -(-halvings * std::f64::consts::LN_2).exp_m1() / (halvings * std::f64::consts::LN_2)
+}
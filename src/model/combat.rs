@@ -0,0 +1,149 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models shooting at things in space. anything with a hull can
+// be a target, once the hull runs out it's gone.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::Au;
+use super::ship::{Component, ShipID};
+use super::station::StationID;
+use super::order::{Order, Unit};
+use super::{GameModel, PlayerID};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Target{
+    Ship(ShipID),
+    Station(StationID),
+}
+
+// cannons have to get this close
+pub const cannon_range:Au = 0.000_1;
+
+impl GameModel{
+    pub fn target_exists(&self, target:Target) -> bool{
+        match target {
+            Target::Ship(ship) => self.ships.contains_key(&ship),
+            Target::Station(station) => self.stations.contains_key(&station),
+        }
+    }
+    pub fn target_owner(&self, target:Target) -> PlayerID{
+        match target {
+            Target::Ship(ship) => self.ships[&ship].owner,
+            Target::Station(station) => self.stations[&station].owner,
+        }
+    }
+    pub fn target_position_at(&self, target:Target, time:&Duration) -> Position{
+        match target {
+            Target::Ship(ship) => self.ships[&ship].movement.calc_position(time, &self.galaxy),
+            Target::Station(station) => self.stations[&station].calc_position(time, &self.galaxy),
+        }
+    }
+    pub fn target_position(&self, target:Target) -> Position{
+        self.target_position_at(target, &self.time)
+    }
+    // the closest foreign thing we know of, for picking targets on the map
+    pub fn nearest_target(&self, player:PlayerID, position:Position, within:Au) -> Option<Target>{
+        let ships = self.ships.values().filter(|x| x.owner != player).map(|x| Target::Ship(x.id));
+        let stations = self.stations.values().filter(|x| x.owner != player).map(|x| Target::Station(x.id));
+        ships.chain(stations)
+            .map(|x| (x, self.target_position(x).distance(position)))
+            .filter(|x| x.1 <= within)
+            .fold(None, |prev:Option<(Target, Au)>, cur| match prev {
+                Some(best) if best.1 <= cur.1 => Some(best),
+                _ => Some(cur)
+            })
+            .map(|x| x.0)
+    }
+    // hull below zero means it's destroyed
+    pub fn damage(&mut self, target:Target, amount:f64){
+        let destroyed = match target {
+            Target::Ship(ship) => match self.ships.get_mut(&ship) {
+                Some(ship) => {
+                    ship.hull -= amount;
+                    ship.hull <= 0.0
+                }
+                None => false
+            },
+            Target::Station(station) => match self.stations.get_mut(&station) {
+                Some(station) => {
+                    station.hull -= amount;
+                    station.hull <= 0.0
+                }
+                None => false
+            },
+        };
+        if destroyed {
+            self.destroy(target);
+        }
+    }
+    pub fn destroy(&mut self, target:Target){
+        match target {
+            Target::Ship(ship) => {
+                self.leave_fleet(ship);
+                self.ships.remove(&ship);
+                for player in self.players.iter_mut(){
+                    player.selected.retain(|x| *x != ship);
+                }
+            }
+            Target::Station(station) => {
+                // the body below loses the housing from now on
+                let below = self.stations.get(&station).and_then(|x| x.movement.anchored_to());
+                if let Some(address) = below {
+                    self.settle_colony(address);
+                }
+                self.stations.remove(&station);
+                if let Some(address) = below {
+                    self.schedule_colony(address);
+                }
+            }
+        }
+    }
+    fn current_target(&self, ship:ShipID) -> Option<Target>{
+        let unit = match self.ships[&ship].fleet {
+            Some(fleet) => Unit::Fleet(fleet),
+            None => Unit::Ship(ship),
+        };
+        match self.unit_orders(unit).remaining().first() {
+            Some(&Order::Attack(target)) if self.target_exists(target) => Some(target),
+            _ => None
+        }
+    }
+    // ships with cannons shoot what they've been ordered to attack
+    pub fn combat_tick(&mut self, interval:Duration){
+        let hours = interval.num_milliseconds() as f64 / Duration::hours(1).num_milliseconds() as f64;
+        let shots:Vec<(Target, f64)> = self.ships.values().filter_map(|ship| {
+            let damage:f64 = ship.components.iter().map(|x| match x {
+                &Component::Cannon(per_hour) => per_hour,
+                _ => 0.0
+            }).sum();
+            if damage <= 0.0 {
+                return None;
+            }
+            let target = self.current_target(ship.id)?;
+            let position = ship.movement.calc_position(&self.time, &self.galaxy);
+            if position.distance(self.target_position(target)) > cannon_range {
+                return None;
+            }
+            Some((target, damage * hours))
+        }).collect();
+        for (target, damage) in shots{
+            self.damage(target, damage);
+        }
+    }
+}
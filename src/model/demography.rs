@@ -0,0 +1,267 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models how populations grow. people are split in age cohorts,
+// children are born to the fertile ones, everybody moves on to the next
+// cohort as they get older and the old die fastest. the cohorts decide the
+// growth rate, which is put in the logistic equation so growth slows down
+// near the carrying capacity and any step size gives the same curve. a
+// handful of colonists needs a couple of fertile adults to grow.
+
+use chrono::Duration;
+
+// children, fertile adults, older adults, elderly
+pub const cohort_count:usize = 4;
+// what an established population looks like, where the flows below settle
+const stable_shares:[f64; cohort_count] = [0.37, 0.355, 0.19, 0.085];
+// children per fertile adult per year, unless the balance says otherwise
+const birth_rate:f64 = 0.1;
+// fraction of the cohort dying per year
+const death_rates:[f64; cohort_count] = [0.002, 0.002, 0.008, 0.06];
+// years spent in a cohort before moving on to the next, the elderly stay
+const cohort_years:[f64; cohort_count - 1] = [15.0, 25.0, 25.0];
+// below this many fertile adults nobody gets born
+const minimum_fertile:f64 = 2.0;
+
+fn years(duration:Duration) -> f64{
+    duration.num_milliseconds() as f64 / Duration::days(365).num_milliseconds() as f64
+}
+// longer aging is done in steps of this, the flows hardly change in a month
+fn aging_step() -> Duration{
+    Duration::days(30)
+}
+
+// the numbers growth is tuned with, picked with the rules before the game
+// starts so the headless simulator can try others
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Balance{
+    // children per fertile adult per year
+    pub birth_rate:f64,
+    // every cohort dies this many times as fast as usual
+    pub mortality:f64,
+}
+impl Balance{
+    pub fn new() -> Balance{
+        Balance{
+            birth_rate,
+            mortality:1.0,
+        }
+    }
+    // for the journal and the command line, name=value
+    pub fn set(&mut self, setting:&str) -> Result<(), String>{
+        let mut parts = setting.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = parts.next().and_then(|x| x.parse::<f64>().ok())
+            .ok_or(format!("{} should look like name=number", setting))?;
+        match name {
+            "birth_rate" => self.birth_rate = value,
+            "mortality" => self.mortality = value,
+            _ => return Err(format!("there's no balance setting {}, only birth_rate and mortality", name)),
+        }
+        Ok(())
+    }
+    // the settings that aren't the usual, the way set reads them
+    pub fn changed(&self) -> Vec<String>{
+        let usual = Balance::new();
+        let mut result = Vec::new();
+        if self.birth_rate != usual.birth_rate {
+            result.push(format!("birth_rate={}", self.birth_rate));
+        }
+        if self.mortality != usual.mortality {
+            result.push(format!("mortality={}", self.mortality));
+        }
+        result
+    }
+}
+impl Default for Balance{
+    fn default() -> Balance{
+        Balance::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cohorts{
+    // fraction of the population in each cohort, adds up to 1
+    pub shares:[f64; cohort_count],
+}
+impl Cohorts{
+    pub fn stable() -> Cohorts{
+        Cohorts{shares:stable_shares}
+    }
+    // a colony ship is full of working age adults
+    pub fn colonists() -> Cohorts{
+        Cohorts{shares:[0.0, 1.0, 0.0, 0.0]}
+    }
+    pub fn fertile(&self, head_count:f64) -> f64{
+        self.shares[1] * head_count
+    }
+    // children born per person per year
+    fn births(&self, head_count:f64, balance:&Balance) -> f64{
+        if self.fertile(head_count) >= minimum_fertile {
+            balance.birth_rate * self.shares[1]
+        }else{
+            0.0
+        }
+    }
+    // births minus deaths per person per year
+    pub fn growth_rate(&self, head_count:f64, balance:&Balance) -> f64{
+        let deaths:f64 = self.shares.iter().zip(death_rates.iter()).map(|(share, rate)| share * rate).sum();
+        self.births(head_count, balance) - deaths * balance.mortality
+    }
+    // what goes in and out of each cohort per person per year: births into
+    // the children, deaths, and people getting too old for their cohort
+    fn flows(&self, head_count:f64, balance:&Balance) -> [f64; cohort_count]{
+        let mut flows = [0.0; cohort_count];
+        flows[0] += self.births(head_count, balance);
+        for i in 0..cohort_count{
+            flows[i] -= self.shares[i] * death_rates[i] * balance.mortality;
+            if i + 1 < cohort_count {
+                let aging = self.shares[i] / cohort_years[i];
+                flows[i] -= aging;
+                flows[i + 1] += aging;
+            }
+        }
+        flows
+    }
+    // the shares after the flows went on for the duration
+    pub fn age(&self, head_count:f64, duration:Duration, balance:&Balance) -> Cohorts{
+        let mut shares = self.shares;
+        let mut left = duration;
+        while left > Duration::zero() {
+            let step = left.min(aging_step());
+            left = left - step;
+            let flows = Cohorts{shares}.flows(head_count, balance);
+            for i in 0..cohort_count{
+                shares[i] = (shares[i] + flows[i] * years(step)).max(0.0);
+            }
+            let total:f64 = shares.iter().sum();
+            if total <= 0.0 {
+                return *self;
+            }
+            for share in shares.iter_mut(){
+                *share /= total;
+            }
+        }
+        Cohorts{shares}
+    }
+}
+
+// change in head count after the duration, solved exactly.
+// written as a difference so a population at capacity stays exactly there
+pub fn logistic_change(head_count:f64, carrying_capacity:f64, rate:f64, duration:Duration) -> f64{
+    if head_count <= 0.0 {
+        return 0.0;
+    }
+    let grown = (rate * years(duration)).exp_m1();
+    if rate <= 0.0 || carrying_capacity <= 0.0 {
+        // dying out, there's no limit to that
+        return head_count * grown;
+    }
+    head_count * (carrying_capacity - head_count) * grown / (carrying_capacity + head_count * grown)
+}
+
+// people times years lived over the duration, the area under the curve above
+pub fn logistic_integral(head_count:f64, carrying_capacity:f64, rate:f64, duration:Duration) -> f64{
+    if head_count <= 0.0 {
+        return 0.0;
+    }
+    let t = years(duration);
+    if rate == 0.0 {
+        return head_count * t;
+    }
+    let grown = (rate * t).exp_m1();
+    if rate < 0.0 || carrying_capacity <= 0.0 {
+        return head_count * grown / rate;
+    }
+    carrying_capacity / rate * (head_count * grown / carrying_capacity).ln_1p()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use crate::model::demography::*;
+
+    #[test]
+    fn steps_add_up_to_one_big_step() {
+        let capacity = 1_000_000.0;
+        let rate = Cohorts::stable().growth_rate(1000.0, &Balance::new());
+        let at_once = 1000.0 + logistic_change(1000.0, capacity, rate, Duration::weeks(52 * 50));
+        let mut stepped = 1000.0;
+        for _ in 0..50 {
+            stepped += logistic_change(stepped, capacity, rate, Duration::weeks(52));
+        }
+        assert!((at_once - stepped).abs() / at_once < 1e-9);
+        assert!(at_once < capacity);
+    }
+
+    #[test]
+    fn overcrowding_shrinks_smoothly() {
+        let rate = Cohorts::stable().growth_rate(2000.0, &Balance::new());
+        let change = logistic_change(2000.0, 1000.0, rate, Duration::weeks(52));
+        assert!(change < 0.0);
+        assert!(2000.0 + change > 1000.0);
+        assert_eq!(logistic_change(1000.0, 1000.0, rate, Duration::weeks(52)), 0.0);
+    }
+
+    #[test]
+    fn area_under_the_curve() {
+        let capacity = 1_000_000.0;
+        let rate = Cohorts::stable().growth_rate(1000.0, &Balance::new());
+        let exact = logistic_integral(1000.0, capacity, rate, Duration::weeks(52 * 200));
+        // midpoints of a week each
+        let mut summed = 0.0;
+        let mut head_count = 1000.0;
+        for _ in 0..(52 * 200) {
+            let half = logistic_change(head_count, capacity, rate, Duration::hours(84));
+            summed += (head_count + half) * 7.0 / 365.0;
+            head_count += logistic_change(head_count, capacity, rate, Duration::weeks(1));
+        }
+        assert!((exact - summed).abs() / exact < 1e-4);
+    }
+
+    #[test]
+    fn a_lonely_colonist_has_no_children() {
+        let colonists = Cohorts::colonists();
+        assert!(colonists.growth_rate(1.0, &Balance::new()) < 0.0);
+        let stable = Cohorts::stable();
+        assert!(colonists.growth_rate(10.0, &Balance::new()) > stable.growth_rate(10.0, &Balance::new()));
+        // nobody to replace the adults, they just get older
+        let alone = colonists.age(1.0, Duration::weeks(52 * 30), &Balance::new());
+        assert_eq!(alone.shares[0], 0.0);
+        assert!(alone.shares[1] < alone.shares[2]);
+        let aged = colonists.age(1000.0, Duration::weeks(52 * 100), &Balance::new());
+        for (aged, stable) in aged.shares.iter().zip(Cohorts::stable().shares.iter()) {
+            assert!((aged - stable).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn children_grow_up() {
+        let colonists = Cohorts::colonists().age(1000.0, Duration::weeks(52), &Balance::new());
+        assert!(colonists.shares[0] > 0.0);
+        assert!(colonists.shares[1] < 1.0);
+        // an orphan grows up without getting company
+        let child = Cohorts {
+            shares: [1.0, 0.0, 0.0, 0.0],
+        };
+        let grown = child.age(1.0, Duration::weeks(52 * 20), &Balance::new());
+        assert!(grown.shares[1] > grown.shares[0]);
+        assert!(grown.shares[2] > 0.0);
+        let total: f64 = grown.shares.iter().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,236 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models fleets, named groups of ships that take orders as one.
+// a fleet flies at the speed of its slowest member and every member keeps
+// an offset from the flagship, so formations survive a move order.
+
+use crate::geometry::Position;
+use super::galaxy::Au;
+use super::ship::ShipID;
+use super::order::{OrderQueue, Unit};
+use super::{GameModel, PlayerID};
+use std::f64;
+
+pub type FleetID = usize;
+#[derive(Clone)]
+pub struct Fleet{
+    pub id:FleetID,
+    pub owner:PlayerID,
+    pub name:String,
+    pub flagship:ShipID,
+    // offset relative to the flagship, the flagship itself sits at 0,0
+    pub members:Vec<(ShipID, Position)>,
+    pub orders:OrderQueue,
+}
+impl Fleet{
+    pub fn new(id:FleetID, owner:PlayerID, ships:&[ShipID]) -> Fleet{
+        let mut result = Fleet{
+            id,
+            owner,
+            name:format!("fleet {}", id),
+            flagship:ships[0],
+            members:Vec::new(),
+            orders:OrderQueue::new(),
+        };
+        result.reform(ships);
+        result
+    }
+    pub fn contains(&self, ship:ShipID) -> bool{
+        self.members.iter().any(|x| x.0 == ship)
+    }
+    pub fn ship_ids(&self) -> Vec<ShipID>{
+        self.members.iter().map(|x| x.0).collect()
+    }
+    pub fn offset_of(&self, ship:ShipID) -> Position{
+        self.members.iter().find(|x| x.0 == ship).map_or(Position::i(0), |x| x.1)
+    }
+    // lay the ships out in a square grid around the flagship,
+    // the first ship becomes the flagship
+    pub fn reform(&mut self, ships:&[ShipID]){
+        let columns = (ships.len() as f64).sqrt().ceil() as usize;
+        let half = (columns as f64 - 1.0) / 2.0;
+        self.members = ships.iter().enumerate().map(|(i, ship)| {
+            let column = (i % columns.max(1)) as f64;
+            let row = (i / columns.max(1)) as f64;
+            (*ship, Position::new(
+                (column - half) * formation_spacing,
+                row * formation_spacing
+            ))
+        }).collect();
+        // make the flagship the origin of the formation
+        let flag_offset = self.members.first().map_or(Position::i(0), |x| x.1);
+        for member in self.members.iter_mut(){
+            member.1 = Position::new(member.1.x - flag_offset.x, member.1.y - flag_offset.y);
+        }
+        self.flagship = ships[0];
+    }
+}
+// about 150 km between ships
+const formation_spacing:Au = 0.000_001;
+
+impl GameModel{
+    // slowest member decides the pace
+    pub fn fleet_speed(&self, fleet:FleetID) -> Au{
+        self.fleets[&fleet].members.iter()
+            .map(|x| self.ships[&x.0].speed)
+            .fold(f64::INFINITY, |prev, speed| if speed < prev { speed } else { prev })
+    }
+    // create a new fleet out of the ships, they leave their old fleets
+    pub fn form_fleet(&mut self, owner:PlayerID, ships:&[ShipID]) -> Option<FleetID>{
+        let ships:Vec<ShipID> = ships.iter().cloned()
+            .filter(|x| self.ships.get(x).is_some_and(|ship| ship.owner == owner)).collect();
+        if ships.is_empty(){
+            return None;
+        }
+        for ship in ships.iter(){
+            self.leave_fleet(*ship);
+        }
+        let id = self.next_fleet_id;
+        self.next_fleet_id += 1;
+        for ship in ships.iter(){
+            // from now on the fleet gives the orders
+            let member = self.ships.get_mut(ship).expect("ship exists");
+            member.fleet = Some(id);
+            member.orders = OrderQueue::new();
+        }
+        self.fleets.insert(id, Fleet::new(id, owner, &ships));
+        Some(id)
+    }
+    // all ships of the other fleets join into, the others are disbanded
+    pub fn merge_fleets(&mut self, into:FleetID, others:&[FleetID]){
+        let mut ships = self.fleets[&into].ship_ids();
+        for other in others.iter().filter(|x| **x != into){
+            if let Some(fleet) = self.fleets.remove(other){
+                ships.append(&mut fleet.ship_ids());
+            }
+        }
+        for ship in ships.iter(){
+            let member = self.ships.get_mut(ship).expect("ship exists");
+            member.fleet = Some(into);
+            member.orders = OrderQueue::new();
+        }
+        if let Some(fleet) = self.fleets.get_mut(&into){
+            fleet.reform(&ships);
+        }
+        // the new formation has to fly to new spots
+        self.wake(Unit::Fleet(into));
+    }
+    // take the ships out of their fleets and put them in a new one
+    pub fn split_fleet(&mut self, owner:PlayerID, ships:&[ShipID]) -> Option<FleetID>{
+        self.form_fleet(owner, ships)
+    }
+    pub fn leave_fleet(&mut self, ship:ShipID){
+        let fleet_id = match self.ships.get_mut(&ship).expect("ship exists").fleet.take(){
+            Some(x) => x,
+            None => return
+        };
+        let remaining:Vec<ShipID> = self.fleets[&fleet_id].ship_ids()
+            .into_iter().filter(|x| *x != ship).collect();
+        if remaining.is_empty(){
+            self.fleets.remove(&fleet_id);
+        }else if let Some(fleet) = self.fleets.get_mut(&fleet_id){
+            fleet.reform(&remaining);
+            self.wake(Unit::Fleet(fleet_id));
+        }
+    }
+    // move the fleet in formation, offsets are kept around the destination
+    pub fn move_fleet(&mut self, fleet:FleetID, destination:Position){
+        let speed = self.fleet_speed(fleet);
+        let time = self.time;
+        for (ship, offset) in self.fleets[&fleet].members.clone(){
+            let target = destination + offset;
+            let galaxy = &self.galaxy;
+            self.ships.get_mut(&ship).expect("ship exists").move_to(time, galaxy, target, speed);
+        }
+    }
+    pub fn selected_fleets(&self, player:PlayerID) -> Vec<FleetID>{
+        let mut result:Vec<FleetID> = self.players[player].selected.iter()
+            .filter_map(|x| self.ships.get(x).and_then(|ship| ship.fleet)).collect();
+        result.sort();
+        result.dedup();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::scenario::testing::{add_ship, sun, sun_model};
+    use crate::model::ship::*;
+    use crate::model::*;
+    use chrono::Duration;
+
+    fn model_with_ships(count: usize) -> GameModel {
+        let mut model = sun_model();
+        for _ in 0..count {
+            add_ship(&mut model, Ship::new(0, 0, sun));
+        }
+        model
+    }
+
+    #[test]
+    fn forming_a_fleet_takes_only_own_ships() {
+        let mut model = model_with_ships(3);
+        model.ships.get_mut(&2).unwrap().owner = 1;
+        assert_eq!(model.form_fleet(0, &[]), None);
+        assert_eq!(model.form_fleet(1, &[0, 1]), None);
+        let fleet = model.form_fleet(0, &[1, 0, 2]).unwrap();
+        assert_eq!(model.fleets[&fleet].ship_ids(), vec![1, 0]);
+        assert_eq!(model.fleets[&fleet].flagship, 1);
+        assert_eq!(model.fleets[&fleet].offset_of(1), Position::i(0));
+        assert_eq!(model.ships[&0].fleet, Some(fleet));
+        assert_eq!(model.ships[&2].fleet, None);
+    }
+
+    #[test]
+    fn merging_disbands_the_other_fleets() {
+        let mut model = model_with_ships(4);
+        let first = model.form_fleet(0, &[0, 1]).unwrap();
+        let second = model.form_fleet(0, &[2, 3]).unwrap();
+        model.merge_fleets(first, &[first, second]);
+        assert!(!model.fleets.contains_key(&second));
+        assert_eq!(model.fleets[&first].ship_ids(), vec![0, 1, 2, 3]);
+        assert!(model.ships.values().all(|x| x.fleet == Some(first)));
+    }
+
+    #[test]
+    fn splitting_leaves_the_rest_behind() {
+        let mut model = model_with_ships(4);
+        let old = model.form_fleet(0, &[0, 1, 2, 3]).unwrap();
+        let new = model.split_fleet(0, &[2, 3]).unwrap();
+        assert_ne!(old, new);
+        assert_eq!(model.fleets[&old].ship_ids(), vec![0, 1]);
+        assert_eq!(model.fleets[&new].ship_ids(), vec![2, 3]);
+        // the last ship leaving disbands the fleet
+        model.split_fleet(0, &[0, 1]);
+        assert!(!model.fleets.contains_key(&old));
+    }
+
+    #[test]
+    fn formations_arrive_in_formation() {
+        let mut model = model_with_ships(4);
+        let fleet = model.form_fleet(0, &[0, 1, 2, 3]).unwrap();
+        let destination = Position::new(0.5, -0.25);
+        model.move_fleet(fleet, destination);
+        let arrived = model.time + Duration::weeks(52 * 10);
+        for (ship, offset) in model.fleets[&fleet].members.clone() {
+            let position = model.ships[&ship].movement.calc_position(&arrived, &model.galaxy);
+            assert!(position.distance(destination + offset) < 1e-12);
+        }
+    }
+}
@@ -24,6 +24,7 @@ use chrono::Duration;
 use crate::geometry::*;
 use crate::model::colony::Colony;
 use std::usize;
+use std::sync::Arc;
 
 // austronomical unit, distance from the earth to the sun. Turns out the milky
 // way fits nicely in a signed f64 au if you take earth as 0.0
@@ -69,7 +70,7 @@ impl StellarBody{
                     Population::new(
                         7456000000
                     )
-                )
+                ).with_resources(1.0, Deposits::new(1.0, 1.0, 1.0))
             ),
             name,
             Duration::days(365),
@@ -106,7 +107,7 @@ pub fn calc_orbit(orbit_duration:&Duration, orbit_distance:Au, time:&Duration) -
     }
 }
 
-#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq)]
+#[derive(Clone,Copy,Debug,Eq,Hash,PartialEq,Ord,PartialOrd)]
 //TODO: perhaps we can implement a custom hash that abuses knowledge of
 // systemsizes to make a simple addition sum of it. (this knowledge should
 // be available at compile time, as long as we don't generate anything randomly)
@@ -142,16 +143,33 @@ impl System{
     }
 }
 
+// systems are shared between copies of the galaxy untill one of them
+// changes, so a snapshot of the model only copies what changed
 #[derive(Clone)]
 pub struct Galaxy{
-    pub systems:Vec<System>
+    pub systems:Vec<Arc<System>>
 }
 impl Galaxy{
     pub fn new(systems:Vec<System>) -> Galaxy{
         Galaxy{
-            systems:systems
+            systems:systems.into_iter().map(Arc::new).collect()
         }
     }
+    // the smallest rectangle around every orbit of every system
+    pub fn extent(&self) -> Rectangle{
+        let spaces = self.systems.iter().map(|x| &x.used_space);
+        let low = spaces.clone().fold(Position::new(f64::MAX, f64::MAX), |prev, disk| {
+            Position::new(prev.x.min(disk.position.x - disk.radius), prev.y.min(disk.position.y - disk.radius))
+        });
+        let high = spaces.fold(Position::new(f64::MIN, f64::MIN), |prev, disk| {
+            Position::new(prev.x.max(disk.position.x + disk.radius), prev.y.max(disk.position.y + disk.radius))
+        });
+        Rectangle{one:low, two:high}
+    }
+    // copies the system first if somebody else is looking at it
+    pub fn system_mut(&mut self, system_id:usize) -> &mut System{
+        Arc::make_mut(&mut self.systems[system_id])
+    }
 }
 use std::ops::{Index,IndexMut};
 impl Index<BodyAddress> for Galaxy {
@@ -163,6 +181,6 @@ impl Index<BodyAddress> for Galaxy {
 
 impl IndexMut<BodyAddress> for Galaxy {
     fn index_mut<'a>(&'a mut self, index: BodyAddress) -> &'a mut StellarBody {
-        &mut self.systems[index.system_id].bodies[index.planet_id]
+        &mut self.system_mut(index.system_id).bodies[index.planet_id]
     }
 }
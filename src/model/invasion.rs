@@ -0,0 +1,245 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models ground combat. troop transports land soldiers on an enemy
+// colony, they fight the garrison and if they win the colony changes owner.
+// the fight follows lanchester's square law, which has a closed form, so the
+// outcome doesn't depend on how big the ticks are.
+
+use chrono::Duration;
+use super::galaxy::{BodyAddress, BodyClass};
+use super::ship::{Component, ShipID};
+use super::colony::{Colony, Population};
+use super::{GameModel, PlayerID};
+
+#[derive(Clone)]
+pub struct GroundBattle{
+    pub attacker:PlayerID,
+    pub troops:f64,
+    pub started:Duration,
+}
+
+// one soldier per this many people in peace time
+const people_per_soldier:f64 = 10_000.0;
+// soldiers killed per enemy soldier per week
+const attacker_lethality:f64 = 0.05;
+// dug in defenders fight better
+const defender_lethality:f64 = 0.08;
+// garrisons recruit back up to strength in about this many weeks
+const garrison_recruit_weeks:f64 = 26.0;
+
+pub fn garrison_for(head_count:i64) -> f64{
+    head_count as f64 / people_per_soldier
+}
+
+fn weeks(duration:Duration) -> f64{
+    duration.num_milliseconds() as f64 / Duration::weeks(1).num_milliseconds() as f64
+}
+
+pub enum BattleOutcome{
+    // attackers, defenders left
+    Ongoing(f64, f64),
+    AttackerWon(f64),
+    DefenderWon(f64),
+}
+
+// weeks untill one side runs out, a draw goes to the defender
+fn weeks_left(attackers:f64, defenders:f64) -> f64{
+    if attackers <= 0.0 || defenders <= 0.0 {
+        return 0.0;
+    }
+    let a = defender_lethality;
+    let d = attacker_lethality;
+    let rate = (a * d).sqrt();
+    if d * attackers * attackers > a * defenders * defenders {
+        // the moment the defenders run out
+        ((d / a).sqrt() * attackers / defenders).recip().atanh() / rate
+    }else{
+        let ratio = (a / d).sqrt() * defenders / attackers;
+        if ratio > 1.0 { ratio.recip().atanh() / rate } else { 0.0 }
+    }
+}
+
+// how long untill the battle is decided, rounded up so the battle is over by then
+pub fn battle_left(attackers:f64, defenders:f64) -> Duration{
+    let millis = weeks_left(attackers, defenders) * Duration::weeks(1).num_milliseconds() as f64;
+    Duration::milliseconds(millis.ceil() as i64 + 1)
+}
+
+// lanchester's square law, the attackers lose defender_lethality * defenders
+// per week and vice versa. solved exactly for the given amount of time.
+pub fn fight(attackers:f64, defenders:f64, duration:Duration) -> BattleOutcome{
+    if defenders <= 0.0 {
+        return BattleOutcome::AttackerWon(attackers);
+    }
+    if attackers <= 0.0 {
+        return BattleOutcome::DefenderWon(defenders);
+    }
+    let a = defender_lethality;
+    let d = attacker_lethality;
+    let rate = (a * d).sqrt();
+    let attacker_at = |t:f64| attackers * (rate * t).cosh() - (a / d).sqrt() * defenders * (rate * t).sinh();
+    let defender_at = |t:f64| defenders * (rate * t).cosh() - (d / a).sqrt() * attackers * (rate * t).sinh();
+    let end = weeks_left(attackers, defenders);
+    if end <= weeks(duration) {
+        if d * attackers * attackers > a * defenders * defenders {
+            return BattleOutcome::AttackerWon(attacker_at(end).max(0.0));
+        }
+        return BattleOutcome::DefenderWon(defender_at(end).max(0.0));
+    }
+    BattleOutcome::Ongoing(attacker_at(weeks(duration)), defender_at(weeks(duration)))
+}
+
+impl GameModel{
+    pub fn troops_aboard(&self, ships:&[ShipID]) -> f64{
+        ships.iter().flat_map(|x| self.ships[x].components.iter()).map(|x| match x {
+            &Component::Troops(count) => count,
+            _ => 0.0
+        }).sum()
+    }
+    // put the soldiers of the ships on the ground, they join the fight
+    // if one is going on already
+    pub fn land_troops(&mut self, owner:PlayerID, ships:&[ShipID], address:BodyAddress){
+        let troops = self.troops_aboard(ships);
+        if troops <= 0.0 {
+            return;
+        }
+        self.settle_colony(address);
+        let time = self.time;
+        let colony = match self.galaxy[address].class {
+            BodyClass::Rocky(ref mut colony) => colony,
+            _ => return
+        };
+        if colony.owner == Some(owner) {
+            // reinforce
+            colony.garrison += troops;
+        }else{
+            match colony.battle {
+                Some(ref mut battle) if battle.attacker == owner => battle.troops += troops,
+                // somebody else is attacking already, wait your turn
+                Some(_) => return,
+                None => colony.battle = Some(GroundBattle{
+                    attacker:owner,
+                    troops,
+                    started:time,
+                }),
+            }
+        }
+        for ship in ships{
+            for component in self.ships.get_mut(ship).expect("ship exists").components.iter_mut(){
+                if let &mut Component::Troops(ref mut count) = component {
+                    *count = 0.0;
+                }
+            }
+        }
+        self.schedule_colony(address);
+    }
+}
+
+impl Colony{
+    // the fight or the recruiting over the interval
+    pub fn ground_tick(&mut self, interval:Duration){
+        let battle = match self.battle.take() {
+            Some(battle) => battle,
+            None => {
+                // recruit back to peace time strength
+                if let Some(ref population) = self.population {
+                    let target = garrison_for(population.head_count);
+                    if self.garrison < target {
+                        let recruited = target * weeks(interval) / garrison_recruit_weeks;
+                        self.garrison = (self.garrison + recruited).min(target);
+                    }
+                }
+                return;
+            }
+        };
+        match fight(battle.troops, self.garrison, interval) {
+            BattleOutcome::Ongoing(attackers, defenders) => {
+                self.garrison = defenders;
+                self.battle = Some(GroundBattle{troops:attackers, ..battle});
+            }
+            BattleOutcome::DefenderWon(defenders) => {
+                self.garrison = defenders;
+            }
+            BattleOutcome::AttackerWon(attackers) => {
+                self.owner = Some(battle.attacker);
+                self.garrison = attackers;
+                // whatever was being build belonged to the old owner
+                self.construction_queue.clear();
+                self.population = match self.population.take() {
+                    Some(population) => Some(population.conquered()),
+                    // nobody lived here, the troops that landed found the colony
+                    None if attackers >= 1.0 => Some(Population::colonists(attackers as i64)),
+                    None => None,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::invasion::*;
+
+    fn step_by_step(attackers: f64, defenders: f64, steps: i64) -> (f64, f64) {
+        let mut state = (attackers, defenders);
+        for _ in 0..steps {
+            state = match fight(state.0, state.1, Duration::weeks(1)) {
+                BattleOutcome::Ongoing(a, d) => (a, d),
+                BattleOutcome::AttackerWon(a) => (a, 0.0),
+                BattleOutcome::DefenderWon(d) => (0.0, d),
+            };
+        }
+        state
+    }
+
+    #[test]
+    fn outcome_independent_of_tick_size() {
+        let (attackers, defenders) = step_by_step(1000.0, 500.0, 20);
+        match fight(1000.0, 500.0, Duration::weeks(20)) {
+            BattleOutcome::AttackerWon(left) => {
+                assert_eq!(defenders, 0.0);
+                assert!((left - attackers).abs() < 1e-6);
+            }
+            _ => panic!("twice the troops should win"),
+        }
+    }
+
+    #[test]
+    fn defenders_hold_with_equal_numbers() {
+        match fight(100.0, 100.0, Duration::weeks(52)) {
+            BattleOutcome::DefenderWon(left) => assert!(left > 0.0),
+            _ => panic!("dug in defenders should win an even fight"),
+        }
+    }
+
+    #[test]
+    fn troops_landing_on_an_empty_body_found_a_colony() {
+        use crate::model::demography::Cohorts;
+        let mut colony = Colony::new_empty(0.5);
+        colony.battle = Some(GroundBattle {
+            attacker: 1,
+            troops: 50.0,
+            started: Duration::zero(),
+        });
+        colony.ground_tick(Duration::hours(1));
+        assert_eq!(colony.owner, Some(1));
+        let population = colony.population.expect("the troops settled");
+        assert_eq!(population.head_count, 50);
+        assert_eq!(population.cohorts, Cohorts::colonists());
+    }
+}
@@ -0,0 +1,205 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models the optional light lag rule. nothing goes faster than
+// light, so what your sensors show is how things were when the light left
+// them, and an order has to travel from the nearest command colony to the
+// ship before it gets executed. colonies only reach so far, beyond that
+// orders have to be passed on by communication satellites.
+// both the sender and the receiver keep moving while the light is underway,
+// so we solve for the meeting time by repeatedly calling calc_position.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::Au;
+use super::order::{Order, Unit};
+use super::station::Station;
+use super::{GameModel, PlayerID};
+
+// 299792.458 km/s in au
+pub const speed_of_light:Au = 0.002_003_988_804;
+// light lag converges really fast since nothing we simulate comes close to c
+const solve_rounds:usize = 4;
+// how far colonies and relays can send orders
+pub const command_range:Au = 10.0;
+
+pub fn light_time(distance:Au) -> Duration{
+    Duration::milliseconds(((distance / speed_of_light) * 1000.0).round() as i64)
+}
+
+// when did the light we see now leave the target, and where was it then
+pub fn retarded_position<F>(observer:Position, now:Duration, target_at:F) -> (Position, Duration)
+    where F: Fn(&Duration) -> Position {
+    let mut emitted = now;
+    let mut position = target_at(&emitted);
+    for _ in 0..solve_rounds {
+        emitted = now - light_time(observer.distance(position));
+        position = target_at(&emitted);
+    }
+    (position, emitted)
+}
+
+// when does a signal send now reach the target, and where is it by then
+pub fn signal_arrival<F>(sender:Position, now:Duration, target_at:F) -> (Position, Duration)
+    where F: Fn(&Duration) -> Position {
+    let mut arrival = now;
+    let mut position = target_at(&arrival);
+    for _ in 0..solve_rounds {
+        arrival = now + light_time(sender.distance(position));
+        position = target_at(&arrival);
+    }
+    (position, arrival)
+}
+
+#[derive(Clone)]
+pub struct PendingOrder{
+    pub arrives_at:Duration,
+    pub unit:Unit,
+    pub order:Order,
+    pub queued:bool,
+}
+
+impl GameModel{
+    // colonies from which orders can be send, and relays that got them by
+    // then, with the time the order leaves from there
+    pub fn command_posts(&self, player:PlayerID) -> Vec<(Position, Duration)>{
+        let mut posts:Vec<(Position, Duration)> = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter(|x| x.get_colony().is_some_and(|colony| {
+                colony.owner == Some(player) && colony.population.is_some()
+            }))
+            .map(|x| (x.calc_position(&self.time), self.time))
+            .collect();
+        let mut relays:Vec<&Station> = self.stations.values()
+            .filter(|x| x.owner == player && x.is_relay())
+            .collect();
+        let galaxy = &self.galaxy;
+        // dijkstra, the relay that can be reached first passes it on next
+        loop {
+            let mut best:Option<(usize, Position, Duration)> = None;
+            for (index, relay) in relays.iter().enumerate(){
+                for post in posts.iter(){
+                    let (position, arrival) = signal_arrival(post.0, post.1, |time| relay.calc_position(time, galaxy));
+                    if post.0.distance(position) > command_range {
+                        continue;
+                    }
+                    if best.is_none_or(|x| arrival < x.2){
+                        best = Some((index, position, arrival));
+                    }
+                }
+            }
+            match best {
+                Some((index, position, arrival)) => {
+                    relays.remove(index);
+                    posts.push((position, arrival));
+                }
+                None => return posts
+            }
+        }
+    }
+    // time at which an order send now reaches the unit,
+    // None if there is nobody to send it
+    pub fn order_arrival(&self, unit:Unit) -> Option<Duration>{
+        let flagship = match unit {
+            Unit::Ship(ship) => ship,
+            Unit::Fleet(fleet) => self.fleets[&fleet].flagship,
+        };
+        let movement = &self.ships[&flagship].movement;
+        let galaxy = &self.galaxy;
+        self.command_posts(self.unit_owner(unit)).into_iter()
+            .map(|post| (post.0, signal_arrival(post.0, post.1, |time| movement.calc_position(time, galaxy))))
+            .filter(|x| x.0.distance((x.1).0) <= command_range)
+            .map(|x| (x.1).1)
+            .min()
+    }
+    // like give_order, but with the light lag rule the order is underway first
+    pub fn send_order(&mut self, unit:Unit, order:Order, queued:bool){
+        if !self.rules.light_lag {
+            return self.give_order(unit, order, queued);
+        }
+        // a unit that lost contact with home never gets the order
+        if let Some(arrival) = self.order_arrival(unit) {
+            self.pending_orders.push(PendingOrder{
+                arrives_at:arrival,
+                unit,
+                order,
+                queued,
+            });
+        }
+    }
+    // hand over the orders that made it to their unit
+    pub fn deliver_orders(&mut self){
+        let time = self.time;
+        let (arrived, underway):(Vec<PendingOrder>, Vec<PendingOrder>) = self.pending_orders
+            .drain(..).partition(|x| x.arrives_at <= time);
+        self.pending_orders = underway;
+        let mut arrived = arrived;
+        // stable, so orders arriving at the same time keep the order they were send in
+        arrived.sort_by_key(|x| x.arrives_at);
+        for pending in arrived{
+            if self.unit_exists(pending.unit){
+                self.give_order(pending.unit, pending.order, pending.queued);
+            }
+        }
+    }
+    pub fn unit_exists(&self, unit:Unit) -> bool{
+        match unit {
+            Unit::Ship(ship) => self.ships.contains_key(&ship),
+            Unit::Fleet(fleet) => self.fleets.contains_key(&fleet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::{center, Position};
+    use crate::model::light_lag::*;
+
+    fn seconds(duration: Duration) -> f64 {
+        duration.num_milliseconds() as f64 / 1000.0
+    }
+    // a twentieth of c, far faster than anything in the game
+    fn moving(time: &Duration) -> Position {
+        Position::new(1.0 + 0.000_1 * seconds(*time), 0.5)
+    }
+
+    #[test]
+    fn light_from_a_standing_target_takes_distance_over_c() {
+        let now = Duration::weeks(1);
+        let target = Position::new(3.0, 4.0);
+        let (position, emitted) = retarded_position(center, now, |_| target);
+        assert_eq!(position, target);
+        assert_eq!(now - emitted, light_time(5.0));
+        assert!((seconds(now - emitted) - 5.0 / speed_of_light).abs() <= 0.001);
+    }
+
+    #[test]
+    fn light_meets_a_moving_target() {
+        let now = Duration::hours(1);
+        let observer = Position::new(-0.5, 0.0);
+        // in seconds, what's left after solve_rounds at this speed
+        let tolerance = 0.01;
+        let (position, emitted) = retarded_position(observer, now, moving);
+        assert_eq!(position, moving(&emitted));
+        let travelled = observer.distance(position) / speed_of_light;
+        assert!((travelled - seconds(now - emitted)).abs() < tolerance);
+
+        let (position, arrival) = signal_arrival(observer, now, moving);
+        assert_eq!(position, moving(&arrival));
+        let travelled = observer.distance(position) / speed_of_light;
+        assert!((travelled - seconds(arrival - now)).abs() < tolerance);
+    }
+}
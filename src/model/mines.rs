@@ -0,0 +1,191 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models minefields. minelayers drop their mines around a point
+// or a body, enemy ships flying trough roll for hits depending on how much
+// of the field they crossed. minesweepers slowly clear fields they're in.
+// the rolls are a hash of the field, ship and time so a game replays the same.
+
+use std::collections::BTreeMap;
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::{Au, Galaxy};
+use super::ship::{Component, Movement, ShipID};
+use super::spatial::{chord_length, SpatialGrid};
+use super::combat::Target;
+use super::{GameModel, PlayerID};
+
+pub type MinefieldID = usize;
+
+#[derive(Clone)]
+pub struct Minefield{
+    pub id:MinefieldID,
+    pub owner:PlayerID,
+    // fixed for a position, orbit to follow a body
+    pub movement:Movement,
+    pub radius:Au,
+    pub mines:f64,
+}
+impl Minefield{
+    pub fn calc_position(&self, time:&Duration, galaxy:&Galaxy) -> Position{
+        self.movement.calc_position(time, galaxy)
+    }
+    fn area(&self) -> f64{
+        std::f64::consts::PI * self.radius * self.radius
+    }
+    // chance a ship flying the given distance trough the field gets hit
+    pub fn hit_chance(&self, crossed:Au) -> f64{
+        1.0 - (-self.mines * mine_trigger_width * crossed / self.area()).exp()
+    }
+}
+
+pub const minefield_radius:Au = 0.001;
+// a mine goes off if a ship passes this close
+const mine_trigger_width:Au = 0.000_01;
+const mine_damage:f64 = 40.0;
+
+// splitmix64, good enough for dice. the same two things at the same time
+// always roll the same
+pub fn roll(first:usize, second:usize, time:Duration) -> f64{
+    let mut z = (first as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (second as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+        ^ (time.num_milliseconds() as u64);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z = z ^ (z >> 31);
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl GameModel{
+    pub fn ship_positions(&self) -> BTreeMap<ShipID, Position>{
+        self.ships.values()
+            .map(|x| (x.id, x.movement.calc_position(&self.time, &self.galaxy)))
+            .collect()
+    }
+    // drop all mines aboard the ships, joining our field if there's one here
+    pub fn lay_mines(&mut self, owner:PlayerID, ships:&[ShipID], movement:Movement){
+        let mut mines = 0.0;
+        for ship in ships{
+            for component in self.ships.get_mut(ship).expect("ship exists").components.iter_mut(){
+                if let &mut Component::MineLayer(ref mut count) = component {
+                    mines += *count;
+                    *count = 0.0;
+                }
+            }
+        }
+        if mines <= 0.0 {
+            return;
+        }
+        let center = movement.calc_position(&self.time, &self.galaxy);
+        let (time, galaxy) = (self.time, &self.galaxy);
+        let existing = self.minefields.values_mut().find(|x| {
+            x.owner == owner && x.calc_position(&time, galaxy).distance(center) <= x.radius * 0.5
+        });
+        if let Some(field) = existing {
+            field.mines += mines;
+            return;
+        }
+        let id = self.next_minefield_id;
+        self.next_minefield_id += 1;
+        self.minefields.insert(id, Minefield{
+            id,
+            owner,
+            movement,
+            radius:minefield_radius,
+            mines,
+        });
+    }
+    // ships that moved from the previous position roll against the fields
+    // they crossed, sweepers clear what they sit in
+    pub fn mine_tick(&mut self, previous:&BTreeMap<ShipID, Position>, interval:Duration){
+        let hours = interval.num_milliseconds() as f64 / Duration::hours(1).num_milliseconds() as f64;
+        let mut grid = SpatialGrid::new(minefield_radius * 4.0);
+        let mut longest_step:Au = 0.0;
+        for ship in self.ships.values(){
+            let now = ship.movement.calc_position(&self.time, &self.galaxy);
+            let before = previous.get(&ship.id).cloned().unwrap_or(now);
+            longest_step = longest_step.max(before.distance(now));
+            grid.insert(before, (ship.id, now));
+        }
+        let mut hits:Vec<(MinefieldID, ShipID)> = Vec::new();
+        let mut swept:Vec<(MinefieldID, f64)> = Vec::new();
+        for field in self.minefields.values(){
+            let center = field.calc_position(&self.time, &self.galaxy);
+            for (before, (ship_id, now)) in grid.query(center, field.radius + longest_step){
+                let ship = &self.ships[&ship_id];
+                if ship.owner == field.owner {
+                    continue;
+                }
+                if now.distance(center) <= field.radius {
+                    let sweep:f64 = ship.components.iter().map(|x| match x {
+                        &Component::MineSweeper(per_hour) => per_hour,
+                        _ => 0.0
+                    }).sum();
+                    if sweep > 0.0 {
+                        swept.push((field.id, sweep * hours));
+                    }
+                }
+                let crossed = chord_length(before, now, center, field.radius);
+                if crossed > 0.0 && roll(field.id, ship_id, self.time) < field.hit_chance(crossed) {
+                    hits.push((field.id, ship_id));
+                }
+            }
+        }
+        for (field, ship) in hits{
+            if let Some(field) = self.minefields.get_mut(&field) {
+                field.mines -= 1.0;
+            }
+            self.damage(Target::Ship(ship), mine_damage);
+        }
+        for (field, count) in swept{
+            if let Some(field) = self.minefields.get_mut(&field) {
+                field.mines -= count;
+            }
+        }
+        self.minefields.retain(|_, x| x.mines >= 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::mines::*;
+    use crate::model::scenario::testing::{add_ship, sun, sun_model};
+    use crate::model::ship::*;
+
+    #[test]
+    fn crossing_a_dense_field_hurts() {
+        let mut model = sun_model();
+        let mut ship = Ship::new(1, 0, sun);
+        let start = Position::new(-1.0, 0.0);
+        let end = Position::new(1.0, 0.0);
+        ship.movement = Movement::Fixed(end);
+        add_ship(&mut model, ship);
+        model.minefields.insert(0, Minefield {
+            id: 0,
+            owner: 0,
+            movement: Movement::Fixed(Position::new(0.0, 0.0)),
+            radius: minefield_radius,
+            mines: 10_000.0,
+        });
+        let mut previous = BTreeMap::new();
+        previous.insert(0, start);
+        model.mine_tick(&previous, Duration::hours(1));
+        assert!(model.ships[&0].hull < 100.0);
+        assert_eq!(model.minefields[&0].mines, 9_999.0);
+    }
+}
@@ -0,0 +1,345 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models guided missiles. unlike ships they don't fly on rails,
+// they accelerate towards where their target is going untill the fuel runs
+// out and go off once close enough. point defence can shoot them down.
+// missiles are stepped in fixed steps counted from their launch, so it
+// doesn't matter which time granularity the player picked.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::Au;
+use super::ship::{Component, Movement, ShipID};
+use super::spatial::SpatialGrid;
+use super::combat::Target;
+use super::mines::roll;
+use super::{GameModel, PlayerID};
+
+pub type MissileID = usize;
+
+#[derive(Clone)]
+pub struct Missile{
+    pub id:MissileID,
+    pub owner:PlayerID,
+    pub target:Target,
+    pub position:Position,
+    // au/s
+    pub velocity:Position,
+    // how much more velocity we can change, au/s
+    pub fuel:Au,
+    // the missile is simulated up to here, may lag behind the model time
+    pub simulated_until:Duration,
+}
+
+// au/s per second, about 150 g
+const missile_acceleration:Au = 0.000_000_01;
+// au/s of velocity change, about 150 km/s
+const missile_fuel:Au = 0.000_001;
+const missile_step_seconds:i64 = 10;
+// the rest of the fuel is kept for corrections
+const closing_speed:Au = missile_fuel * 0.4;
+// goes off this close to the target
+pub const fuse_range:Au = 0.000_000_1;
+const missile_damage:f64 = 60.0;
+// point defence reaches this far
+pub const point_defence_range:Au = 0.000_1;
+// launchers only fire at targets this close
+pub const missile_range:Au = 0.5;
+
+fn length(x:Position) -> f64{
+    (x.x * x.x + x.y * x.y).sqrt()
+}
+
+// closest the relative motion gets during one step, both move in a straight line
+fn closest_approach(offset_start:Position, offset_end:Position) -> Au{
+    let dx = offset_end.x - offset_start.x;
+    let dy = offset_end.y - offset_start.y;
+    let moved = dx * dx + dy * dy;
+    if moved <= 0.0 {
+        return length(offset_start);
+    }
+    let t = (-(offset_start.x * dx + offset_start.y * dy) / moved).clamp(0.0, 1.0);
+    length(Position::new(offset_start.x + dx * t, offset_start.y + dy * t))
+}
+
+enum Fate{
+    Flying,
+    Hit,
+    ShotDown,
+    Lost,
+}
+
+// built once per tick instead of every step, only the ships that move
+// have to be placed again
+struct PointDefence{
+    fixed:SpatialGrid<(ShipID, f64)>,
+    moving:Vec<(ShipID, f64)>,
+}
+
+impl GameModel{
+    // one missile from every loaded launcher at the target
+    pub fn launch_missiles(&mut self, owner:PlayerID, ships:&[ShipID], target:Target){
+        if !self.target_exists(target) {
+            return;
+        }
+        for ship in ships{
+            let mut launchers = 0;
+            for component in self.ships.get_mut(ship).expect("ship exists").components.iter_mut(){
+                if let &mut Component::MissileLauncher(ref mut loaded) = component {
+                    if *loaded >= 1.0 {
+                        *loaded -= 1.0;
+                        launchers += 1;
+                    }
+                }
+            }
+            let ship = &self.ships[ship];
+            let position = ship.movement.calc_position(&self.time, &self.galaxy);
+            // start with the ship's own velocity
+            let later = ship.movement.calc_position(&(self.time + Duration::seconds(1)), &self.galaxy);
+            let velocity = Position::new(later.x - position.x, later.y - position.y);
+            for _ in 0..launchers{
+                let id = self.next_missile_id;
+                self.next_missile_id += 1;
+                self.missiles.insert(id, Missile{
+                    id,
+                    owner,
+                    target,
+                    position,
+                    velocity,
+                    fuel:missile_fuel,
+                    simulated_until:self.time,
+                });
+            }
+        }
+    }
+    fn step_missile(&self, missile:&mut Missile, defence:&PointDefence, moving:&SpatialGrid<(ShipID, f64)>) -> Fate{
+        if !self.target_exists(missile.target) {
+            return Fate::Lost;
+        }
+        let step = Duration::seconds(missile_step_seconds);
+        let seconds = missile_step_seconds as f64;
+        let start = missile.simulated_until;
+        let end = start + step;
+        let target_start = self.target_position_at(missile.target, &start);
+        let target_end = self.target_position_at(missile.target, &end);
+        let target_velocity = Position::new(
+            (target_end.x - target_start.x) / seconds,
+            (target_end.y - target_start.y) / seconds
+        );
+        // seen from the target we fly straight at it, which is a collision
+        // course as long as it doesn't change direction
+        let to_aim = Position::new(target_start.x - missile.position.x, target_start.y - missile.position.y);
+        let to_aim_length = length(to_aim).max(f64::MIN_POSITIVE);
+        // close in on top of the target's velocity
+        let desired = Position::new(
+            target_velocity.x + to_aim.x / to_aim_length * closing_speed,
+            target_velocity.y + to_aim.y / to_aim_length * closing_speed
+        );
+        let change = Position::new(desired.x - missile.velocity.x, desired.y - missile.velocity.y);
+        let wanted = length(change);
+        let burn = wanted.min(missile_acceleration * seconds).min(missile.fuel);
+        if burn > 0.0 {
+            missile.velocity = Position::new(
+                missile.velocity.x + change.x / wanted * burn,
+                missile.velocity.y + change.y / wanted * burn
+            );
+            missile.fuel -= burn;
+        }
+        let from = missile.position;
+        missile.position = Position::new(
+            from.x + missile.velocity.x * seconds,
+            from.y + missile.velocity.y * seconds
+        );
+        missile.simulated_until = end;
+        let offset_start = Position::new(target_start.x - from.x, target_start.y - from.y);
+        let offset_end = Position::new(target_end.x - missile.position.x, target_end.y - missile.position.y);
+        if closest_approach(offset_start, offset_end) <= fuse_range {
+            return Fate::Hit;
+        }
+        let near = defence.fixed.query(missile.position, point_defence_range).into_iter()
+            .chain(moving.query(missile.position, point_defence_range));
+        for (_, (ship, chance)) in near{
+            // destroyed earlier this step
+            let owner = match self.ships.get(&ship) {
+                Some(ship) => ship.owner,
+                None => continue
+            };
+            if owner != missile.owner && roll(missile.id, ship, end) < chance {
+                return Fate::ShotDown;
+            }
+        }
+        // out of fuel and falling behind, it's never coming back
+        let closing_x = missile.velocity.x - target_velocity.x;
+        let closing_y = missile.velocity.y - target_velocity.y;
+        if missile.fuel <= 0.0 && (offset_end.x * closing_x + offset_end.y * closing_y) < 0.0 {
+            return Fate::Lost;
+        }
+        Fate::Flying
+    }
+    // ships with point defence and their chance to hit per step, the ones
+    // that stay put go in the grid right away
+    fn point_defence(&self) -> PointDefence{
+        let mut defence = PointDefence{
+            fixed:SpatialGrid::new(point_defence_range * 4.0),
+            moving:Vec::new(),
+        };
+        for ship in self.ships.values(){
+            let chance:f64 = ship.components.iter().map(|x| match x {
+                &Component::PointDefence(chance) => chance,
+                _ => 0.0
+            }).sum();
+            if chance <= 0.0 {
+                continue;
+            }
+            match ship.movement {
+                Movement::Fixed(position) => defence.fixed.insert(position, (ship.id, chance)),
+                _ => defence.moving.push((ship.id, chance)),
+            }
+        }
+        defence
+    }
+    // where the moving point defence is at the given time
+    fn moving_defence_at(&self, defence:&PointDefence, time:&Duration) -> SpatialGrid<(ShipID, f64)>{
+        let mut grid = SpatialGrid::new(point_defence_range * 4.0);
+        for &(id, chance) in defence.moving.iter(){
+            if let Some(ship) = self.ships.get(&id) {
+                grid.insert(ship.movement.calc_position(time, &self.galaxy), (id, chance));
+            }
+        }
+        grid
+    }
+    // steps every missile untill it's caught up with the model time
+    pub fn missile_tick(&mut self){
+        let step = Duration::seconds(missile_step_seconds);
+        let mut missiles:Vec<Missile> = self.missiles.values().cloned().collect();
+        let mut gone:Vec<MissileID> = Vec::new();
+        // step all missiles together so they see the same point defence
+        let mut step_end = missiles.iter().map(|x| x.simulated_until).min().map(|x| x + step);
+        // ships don't change course while missiles are being stepped
+        let defence = self.point_defence();
+        while let Some(end) = step_end {
+            if end > self.time {
+                break;
+            }
+            let moving = self.moving_defence_at(&defence, &end);
+            for missile in missiles.iter_mut().filter(|x| x.simulated_until + step == end){
+                if gone.contains(&missile.id) {
+                    continue;
+                }
+                match self.step_missile(missile, &defence, &moving) {
+                    Fate::Flying => {}
+                    // right away, so later steps see the damage
+                    Fate::Hit => {
+                        self.damage(missile.target, missile_damage);
+                        gone.push(missile.id);
+                    }
+                    Fate::ShotDown | Fate::Lost => gone.push(missile.id),
+                }
+            }
+            step_end = missiles.iter().filter(|x| !gone.contains(&x.id))
+                .map(|x| x.simulated_until).min().map(|x| x + step);
+        }
+        for missile in missiles{
+            if gone.contains(&missile.id) {
+                self.missiles.remove(&missile.id);
+            }else{
+                self.missiles.insert(missile.id, missile);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::combat::Target;
+    use crate::model::scenario::testing::{add_ship, sun, sun_model};
+    use crate::model::ship::*;
+    use crate::model::*;
+    use chrono::Duration;
+
+    fn duel() -> GameModel {
+        let mut model = sun_model();
+        let mut shooter = Ship::new(0, 0, sun).with_component(Component::MissileLauncher(4.0));
+        shooter.movement = Movement::Fixed(Position::new(0.0, 0.0));
+        let mut target = Ship::new(1, 0, sun).with_component(Component::PointDefence(0.02));
+        target.movement = Movement::Travel(
+            Duration::zero(),
+            Position::new(0.05, 0.0),
+            Position::new(0.05, 1.0),
+            0.000_000_1,
+        );
+        add_ship(&mut model, shooter);
+        add_ship(&mut model, target);
+        for _ in 0..4 {
+            model.launch_missiles(0, &[0], Target::Ship(1));
+        }
+        model
+    }
+
+    fn run(mut model: GameModel, tick: Duration, ticks: i32) -> GameModel {
+        for _ in 0..ticks {
+            model.time = model.time + tick;
+            model.missile_tick();
+        }
+        model
+    }
+
+    #[test]
+    fn point_defence_shoots_missiles_down() {
+        for movement in [
+            Movement::Fixed(Position::new(0.05, 0.0)),
+            Movement::Travel(
+                Duration::zero(),
+                Position::new(0.05, 0.0),
+                Position::new(0.05, 1.0),
+                0.000_000_1,
+            ),
+        ]
+        .iter()
+        {
+            let mut model = duel();
+            {
+                let target = model.ships.get_mut(&1).unwrap();
+                target.components = vec![Component::PointDefence(1.0)];
+                target.movement = movement.clone();
+            }
+            let model = run(model, Duration::days(2), 1);
+            assert!(model.missiles.is_empty());
+            assert_eq!(model.ships.get(&1).map(|x| x.hull), Some(100.0));
+        }
+    }
+
+    #[test]
+    fn same_outcome_for_any_granularity() {
+        let coarse = run(duel(), Duration::days(2), 1);
+        let fine = run(duel(), Duration::minutes(7), 2 * 24 * 60 / 7 + 1);
+        let hull = |model: &GameModel| model.ships.get(&1).map(|x| x.hull);
+        assert_eq!(hull(&coarse), hull(&fine));
+        assert!(hull(&coarse).is_none_or(|x| x < 100.0));
+        let left = |model: &GameModel| {
+            model
+                .missiles
+                .values()
+                .map(|x| (x.id, x.position))
+                .collect::<Vec<_>>()
+        };
+        assert!(left(&coarse).is_empty());
+        assert!(left(&fine).is_empty());
+    }
+}
@@ -0,0 +1,650 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models standing orders, every ship and fleet has a queue of them.
+// the queue gets executed as part of the simulation tick, so a ship keeps
+// doing what it was told even if nobody looks at the map.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::{Au, BodyAddress, BodyClass};
+use super::ship::{Movement, ShipID};
+use super::fleet::FleetID;
+use super::combat::{cannon_range, Target};
+use super::missile::missile_range;
+use super::schedule::Event;
+use std::collections::BTreeSet;
+use super::{GameModel, PlayerID};
+
+#[derive(Clone)]
+pub enum Order{
+    MoveTo(Position),
+    // fly to the body and stay in orbit untill there is a next order
+    Orbit(BodyAddress),
+    // fly between the waypoints forever, the usize is the next waypoint
+    Patrol(Vec<Position>, usize),
+    Escort(ShipID),
+    // None picks the nearest place to refuel once the order starts
+    Refuel(Option<BodyAddress>),
+    // start over at the first order
+    Loop,
+    // visit unsurveyed bodies, nearest first, untill there are none left
+    Explore,
+    // fly to the colony and land all troops aboard
+    Invade(BodyAddress),
+    // stay within cannon range untill it's destroyed
+    Attack(Target),
+    // drop the mines aboard around the body, or right here if None
+    LayMines(Option<BodyAddress>),
+    // get within missile range and fire one salvo
+    FireMissiles(Target),
+}
+
+#[derive(Clone)]
+pub struct OrderQueue{
+    pub orders:Vec<Order>,
+    pub current:usize,
+}
+impl OrderQueue{
+    pub fn new() -> OrderQueue{
+        OrderQueue{
+            orders:Vec::new(),
+            current:0,
+        }
+    }
+    pub fn is_idle(&self) -> bool{
+        self.current >= self.orders.len()
+    }
+    pub fn remaining(&self) -> &[Order]{
+        if self.is_idle() {
+            &[]
+        }else{
+            &self.orders[self.current..]
+        }
+    }
+    // queued means shift was held, otherwise the queue is replaced
+    pub fn give(&mut self, order:Order, queued:bool){
+        if queued && !self.is_idle() {
+            // shift clicking more waypoints adds them to the last patrol
+            if let Order::Patrol(ref added, _) = order {
+                if let Some(&mut Order::Patrol(ref mut waypoints, _)) = self.orders.last_mut() {
+                    waypoints.extend(added.iter().cloned());
+                    return;
+                }
+            }
+        }
+        if !queued {
+            self.orders.clear();
+            self.current = 0;
+        }else if self.is_idle(){
+            // throw away what's done so it doesn't get looped over
+            self.orders.clear();
+            self.current = 0;
+        }
+        self.orders.push(order);
+    }
+    fn advance(&mut self){
+        self.current += 1;
+        if self.is_idle(){
+            self.orders.clear();
+            self.current = 0;
+        }
+    }
+}
+impl Default for OrderQueue{
+    fn default() -> OrderQueue{
+        OrderQueue::new()
+    }
+}
+
+// anything that can take orders
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Unit{
+    Ship(ShipID),
+    Fleet(FleetID),
+}
+
+// close enough to count as being there, bodies move while we aim for them
+const arrival_distance:Au = 0.001;
+// how many orders we may complete in one tick
+const max_orders_per_tick:usize = 16;
+
+enum Progress{
+    Done,
+    Busy,
+}
+
+impl GameModel{
+    pub fn unit_owner(&self, unit:Unit) -> PlayerID{
+        match unit {
+            Unit::Ship(ship) => self.ships[&ship].owner,
+            Unit::Fleet(fleet) => self.fleets[&fleet].owner,
+        }
+    }
+    pub fn unit_position(&self, unit:Unit) -> Position{
+        let ship = match unit {
+            Unit::Ship(ship) => ship,
+            Unit::Fleet(fleet) => self.fleets[&fleet].flagship,
+        };
+        self.ships[&ship].movement.calc_position(&self.time, &self.galaxy)
+    }
+    pub fn unit_speed(&self, unit:Unit) -> Au{
+        match unit {
+            Unit::Ship(ship) => self.ships[&ship].speed,
+            Unit::Fleet(fleet) => self.fleet_speed(fleet),
+        }
+    }
+    pub fn unit_ships(&self, unit:Unit) -> Vec<ShipID>{
+        match unit {
+            Unit::Ship(ship) => vec![ship],
+            Unit::Fleet(fleet) => self.fleets[&fleet].ship_ids(),
+        }
+    }
+    pub fn unit_orders(&self, unit:Unit) -> &OrderQueue{
+        match unit {
+            Unit::Ship(ship) => &self.ships[&ship].orders,
+            Unit::Fleet(fleet) => &self.fleets[&fleet].orders,
+        }
+    }
+    fn unit_orders_mut(&mut self, unit:Unit) -> &mut OrderQueue{
+        match unit {
+            Unit::Ship(ship) => &mut self.ships.get_mut(&ship).expect("ship exists").orders,
+            Unit::Fleet(fleet) => &mut self.fleets.get_mut(&fleet).expect("fleet exists").orders,
+        }
+    }
+    fn unit_move(&mut self, unit:Unit, destination:Position){
+        match unit {
+            Unit::Ship(ship) => {
+                let time = self.time;
+                let speed = self.ships[&ship].speed;
+                let galaxy = &self.galaxy;
+                self.ships.get_mut(&ship).expect("ship exists").move_to(time, galaxy, destination, speed);
+            }
+            Unit::Fleet(fleet) => self.move_fleet(fleet, destination),
+        }
+    }
+    fn unit_has_arrived(&self, unit:Unit, destination:Position) -> bool{
+        self.unit_position(unit).distance(destination) <= arrival_distance
+    }
+    // the units of the selection, ships in a fleet are commanded trough it
+    pub fn selected_units(&self, player:PlayerID) -> Vec<Unit>{
+        let mut result:Vec<Unit> = Vec::new();
+        for ship in self.players[player].selected.iter().filter(|x| self.ships.contains_key(x)){
+            let unit = match self.ships[ship].fleet {
+                Some(fleet) => Unit::Fleet(fleet),
+                None => Unit::Ship(*ship),
+            };
+            if !result.contains(&unit){
+                result.push(unit);
+            }
+        }
+        result
+    }
+    pub fn give_order(&mut self, unit:Unit, order:Order, queued:bool){
+        if let Order::Escort(target) = order {
+            // can't escort yourself
+            if self.unit_ships(unit).contains(&target){
+                return;
+            }
+        }
+        if let Order::Attack(target) | Order::FireMissiles(target) = order {
+            if !self.target_exists(target) || self.target_owner(target) == self.unit_owner(unit){
+                return;
+            }
+        }
+        self.unit_orders_mut(unit).give(order, queued);
+        self.wake(unit);
+    }
+    pub fn order_selection(&mut self, player:PlayerID, order:Order, queued:bool){
+        for unit in self.selected_units(player){
+            self.send_order(unit, order.clone(), queued);
+        }
+    }
+    // where the body will be once we get there, a few rounds of guessing
+    // is good enough since we re-aim every tick anyway
+    fn intercept(&self, from:Position, speed:Au, address:BodyAddress) -> Position{
+        let body = &self.galaxy[address];
+        let mut target = body.calc_position(&self.time);
+        if speed <= 0.0 {
+            return target;
+        }
+        for _ in 0..3 {
+            let seconds = from.distance(target) / speed;
+            let arrival = self.time + Duration::milliseconds((seconds * 1000.0) as i64);
+            target = body.calc_position(&arrival);
+        }
+        target
+    }
+    // nearest body where the owner may refuel, own colonies and gas giants
+    pub fn nearest_refuel(&self, owner:PlayerID, from:Position) -> Option<BodyAddress>{
+        self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter(|body| match body.class {
+                BodyClass::GasGiant => true,
+                BodyClass::Rocky(ref colony) => colony.owner == Some(owner),
+                BodyClass::Star => false,
+            })
+            .map(|body| (body.address, body.calc_position(&self.time).distance(from)))
+            .fold(None, |prev:Option<(BodyAddress, Au)>, cur| match prev {
+                Some(best) if best.1 <= cur.1 => Some(best),
+                _ => Some(cur)
+            })
+            .map(|x| x.0)
+    }
+    fn fly_to_body(&mut self, unit:Unit, address:BodyAddress) -> Progress{
+        let from = self.unit_position(unit);
+        let target = self.intercept(from, self.unit_speed(unit), address);
+        let body_now = self.galaxy[address].calc_position(&self.time);
+        if self.unit_has_arrived(unit, body_now){
+            let time = self.time;
+            for ship in self.unit_ships(unit){
+                let galaxy = &self.galaxy;
+                self.ships.get_mut(&ship).expect("ship exists").enter_orbit(time, galaxy, address);
+            }
+            return Progress::Done;
+        }
+        self.unit_move(unit, target);
+        Progress::Busy
+    }
+    fn execute_order(&mut self, unit:Unit, order:Order) -> Progress{
+        match order {
+            Order::MoveTo(destination) => {
+                if self.unit_has_arrived(unit, destination){
+                    return Progress::Done;
+                }
+                if !self.is_travelling_to(unit, destination){
+                    self.unit_move(unit, destination);
+                }
+                Progress::Busy
+            }
+            Order::Orbit(address) => {
+                if self.is_orbiting(unit, address){
+                    return Progress::Done;
+                }
+                self.fly_to_body(unit, address)
+            }
+            Order::Patrol(mut waypoints, next) => {
+                if waypoints.is_empty(){
+                    return Progress::Done;
+                }
+                if waypoints.len() == 1 {
+                    // patrol between here and there
+                    waypoints.insert(0, self.unit_position(unit));
+                }
+                let index = next % waypoints.len();
+                let destination = waypoints[index];
+                let next = if self.unit_has_arrived(unit, destination){
+                    (index + 1) % waypoints.len()
+                }else{
+                    index
+                };
+                if !self.is_travelling_to(unit, waypoints[next]){
+                    self.unit_move(unit, waypoints[next]);
+                }
+                self.replace_current(unit, Order::Patrol(waypoints, next));
+                Progress::Busy
+            }
+            Order::Escort(target) => {
+                if !self.ships.contains_key(&target){
+                    return Progress::Done;
+                }
+                let destination = self.ships[&target].movement.calc_position(&self.time, &self.galaxy);
+                if !self.unit_has_arrived(unit, destination) && !self.is_heading_near(unit, destination){
+                    self.unit_move(unit, destination);
+                }
+                Progress::Busy
+            }
+            Order::Refuel(None) => {
+                let owner = self.unit_owner(unit);
+                let from = self.unit_position(unit);
+                match self.nearest_refuel(owner, from) {
+                    Some(address) => {
+                        self.replace_current(unit, Order::Refuel(Some(address)));
+                        self.execute_order(unit, Order::Refuel(Some(address)))
+                    }
+                    None => Progress::Done
+                }
+            }
+            Order::Refuel(Some(address)) => {
+                if let Progress::Busy = self.fly_to_body(unit, address){
+                    return Progress::Busy;
+                }
+                for ship in self.unit_ships(unit){
+                    let ship = self.ships.get_mut(&ship).expect("ship exists");
+                    ship.fuel = ship.fuel_capacity;
+                }
+                Progress::Done
+            }
+            Order::Loop => {
+                self.unit_orders_mut(unit).current = 0;
+                Progress::Busy
+            }
+            Order::Invade(address) => {
+                if let Progress::Busy = self.fly_to_body(unit, address){
+                    return Progress::Busy;
+                }
+                let owner = self.unit_owner(unit);
+                let ships = self.unit_ships(unit);
+                self.land_troops(owner, &ships, address);
+                Progress::Done
+            }
+            Order::Attack(target) => {
+                if !self.target_exists(target){
+                    return Progress::Done;
+                }
+                let destination = self.target_position(target);
+                // aim a bit closer so a moving target doesn't escape right away
+                if self.unit_position(unit).distance(destination) > cannon_range * 0.5
+                    && !self.is_heading_near(unit, destination) {
+                    self.unit_move(unit, destination);
+                }
+                Progress::Busy
+            }
+            Order::FireMissiles(target) => {
+                if !self.target_exists(target){
+                    return Progress::Done;
+                }
+                let destination = self.target_position(target);
+                if self.unit_position(unit).distance(destination) > missile_range {
+                    self.unit_move(unit, destination);
+                    return Progress::Busy;
+                }
+                let owner = self.unit_owner(unit);
+                let ships = self.unit_ships(unit);
+                self.launch_missiles(owner, &ships, target);
+                Progress::Done
+            }
+            Order::LayMines(place) => {
+                let movement = match place {
+                    Some(address) => {
+                        if let Progress::Busy = self.fly_to_body(unit, address){
+                            return Progress::Busy;
+                        }
+                        Movement::Orbit(self.time, address)
+                    }
+                    None => Movement::Fixed(self.unit_position(unit)),
+                };
+                let owner = self.unit_owner(unit);
+                let ships = self.unit_ships(unit);
+                self.lay_mines(owner, &ships, movement);
+                Progress::Done
+            }
+            Order::Explore => {
+                let owner = self.unit_owner(unit);
+                let from = self.unit_position(unit);
+                match self.nearest_unsurveyed(owner, from) {
+                    // the survey tick will mark it once we're close,
+                    // after which we go for the next one
+                    Some(address) => {
+                        self.fly_to_body(unit, address);
+                        Progress::Busy
+                    }
+                    None => Progress::Done
+                }
+            }
+        }
+    }
+    fn replace_current(&mut self, unit:Unit, order:Order){
+        let queue = self.unit_orders_mut(unit);
+        let current = queue.current;
+        queue.orders[current] = order;
+    }
+    // where the flagship is flying and its place in the fleet
+    fn course(&self, unit:Unit) -> Option<(Position, Position)>{
+        let ship = match unit {
+            Unit::Ship(ship) => ship,
+            Unit::Fleet(fleet) => self.fleets[&fleet].flagship,
+        };
+        let offset = match unit {
+            Unit::Ship(_) => Position::i(0),
+            Unit::Fleet(fleet) => self.fleets[&fleet].offset_of(ship),
+        };
+        match self.ships[&ship].movement {
+            Movement::Travel(_, _, to, _) => Some((to, offset)),
+            _ => None
+        }
+    }
+    fn is_travelling_to(&self, unit:Unit, destination:Position) -> bool{
+        self.course(unit).is_some_and(|(to, offset)| to == destination + offset)
+    }
+    // on the way to about there already, a target that hardly moved
+    // doesn't need a new course
+    fn is_heading_near(&self, unit:Unit, destination:Position) -> bool{
+        self.course(unit).is_some_and(|(to, offset)| to.distance(destination + offset) <= arrival_distance)
+    }
+    fn is_orbiting(&self, unit:Unit, address:BodyAddress) -> bool{
+        self.unit_ships(unit).iter().all(|x| match self.ships[x].movement {
+            Movement::Orbit(_, orbiting) => orbiting == address,
+            _ => false
+        })
+    }
+    // false if there's nothing to do untill the unit gets new orders
+    fn step_orders(&mut self, unit:Unit) -> bool{
+        let mut looped = false;
+        for _ in 0..max_orders_per_tick {
+            let order = match self.unit_orders(unit).remaining().first() {
+                Some(order) => order.clone(),
+                None => return false
+            };
+            let was_loop = matches!(order, Order::Loop);
+            match self.execute_order(unit, order) {
+                Progress::Done => self.unit_orders_mut(unit).advance(),
+                // back at the loop without anything keeping us busy, going
+                // round again would do the same
+                Progress::Busy if was_loop && looped => return false,
+                // a loop just jumped back, execute the first order right away
+                Progress::Busy if was_loop => looped = true,
+                Progress::Busy => return true,
+            }
+        }
+        true
+    }
+    // ships in a fleet follow the fleet's orders instead of their own
+    fn takes_orders(&self, unit:Unit) -> bool{
+        match unit {
+            Unit::Ship(ship) => self.ships.get(&ship).is_some_and(|x| x.fleet.is_none()),
+            Unit::Fleet(fleet) => self.fleets.contains_key(&fleet),
+        }
+    }
+    // when the unit should look at its orders again. flying somewhere fixed
+    // or sitting in orbit there's nothing to do untill we get there or get
+    // a new order, anything else is checked every step
+    fn next_order_check(&self, unit:Unit) -> Option<Duration>{
+        let order = self.unit_orders(unit).remaining().first()?;
+        let arrival = self.unit_ships(unit).iter()
+            .map(|x| self.ships[x].movement.arrival_time())
+            .try_fold(self.time, |prev, cur| cur.map(|cur| prev.max(cur)));
+        let next_step = self.time + Duration::milliseconds(1);
+        match order {
+            &Order::MoveTo(_) | &Order::Patrol(_, _) => match arrival {
+                Some(time) if time > self.time => Some(time),
+                _ => Some(next_step)
+            },
+            &Order::Orbit(address) if self.is_orbiting(unit, address) => None,
+            _ => Some(next_step)
+        }
+    }
+    // executes the standing orders of the units that are due
+    pub fn order_tick(&mut self, due:BTreeSet<Unit>){
+        for unit in due{
+            if !self.takes_orders(unit) {
+                continue;
+            }
+            if !self.step_orders(unit) {
+                continue;
+            }
+            if let Some(time) = self.next_order_check(unit) {
+                self.schedule.at(time, Event::Orders(unit));
+            }
+        }
+    }
+    // the path the unit is going to fly, for drawing
+    pub fn order_waypoints(&self, unit:Unit) -> Vec<Position>{
+        let mut result = vec![self.unit_position(unit)];
+        let queue = self.unit_orders(unit);
+        for order in queue.remaining(){
+            match order {
+                &Order::MoveTo(destination) => result.push(destination),
+                &Order::Orbit(address) | &Order::Refuel(Some(address)) | &Order::Invade(address)
+                    | &Order::LayMines(Some(address)) =>
+                    result.push(self.galaxy[address].calc_position(&self.time)),
+                &Order::Patrol(ref waypoints, next) => {
+                    let len = waypoints.len();
+                    for i in 0..len {
+                        result.push(waypoints[(next + i) % len]);
+                    }
+                }
+                &Order::Escort(target) => if self.ships.contains_key(&target) {
+                    result.push(self.ships[&target].movement.calc_position(&self.time, &self.galaxy));
+                },
+                &Order::Attack(target) | &Order::FireMissiles(target) => if self.target_exists(target) {
+                    result.push(self.target_position(target));
+                },
+                &Order::Refuel(None) | &Order::LayMines(None) => {}
+                &Order::Explore => if let Some(address) = self.nearest_unsurveyed(
+                    self.unit_owner(unit), *result.last().expect("starts with the unit")
+                ) {
+                    result.push(self.galaxy[address].calc_position(&self.time));
+                },
+                &Order::Loop => if let Some(first) = result.get(1).cloned() {
+                    result.push(first);
+                },
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::{center, Position};
+    use crate::model::galaxy::*;
+    use crate::model::order::*;
+    use crate::model::scenario::testing::{add_ship, sun, sun_model};
+    use crate::model::ship::*;
+    use chrono::Duration;
+    use std::collections::BTreeSet;
+
+    fn lone_ship_at(position: Position) -> GameModel {
+        let mut model = sun_model();
+        let mut ship = Ship::new(0, 0, sun);
+        ship.movement = Movement::Travel(model.time, position, position, ship.speed);
+        add_ship(&mut model, ship);
+        model
+    }
+    fn tick(model: &mut GameModel) {
+        let mut due = BTreeSet::new();
+        due.insert(Unit::Ship(0));
+        model.order_tick(due);
+    }
+    fn patrol_of(model: &GameModel) -> (Vec<Position>, usize) {
+        match model.ships[&0].orders.remaining().first() {
+            Some(&Order::Patrol(ref waypoints, next)) => (waypoints.clone(), next),
+            _ => panic!("should be patrolling"),
+        }
+    }
+
+    #[test]
+    fn queue_advances_and_forgets_what_is_done() {
+        let mut queue = OrderQueue::new();
+        queue.give(Order::MoveTo(center), false);
+        queue.give(Order::Loop, true);
+        assert_eq!(queue.remaining().len(), 2);
+        queue.advance();
+        assert_eq!(queue.current, 1);
+        queue.advance();
+        assert!(queue.is_idle());
+        assert!(queue.orders.is_empty());
+        // queueing on an idle queue starts a new one
+        queue.give(Order::MoveTo(center), true);
+        queue.give(Order::Orbit(BodyAddress { system_id: 0, planet_id: 0 }), false);
+        assert_eq!(queue.orders.len(), 1);
+    }
+
+    #[test]
+    fn shift_adds_waypoints_to_the_patrol() {
+        let mut queue = OrderQueue::new();
+        let (a, b) = (Position::new(1.0, 0.0), Position::new(0.0, 1.0));
+        queue.give(Order::Patrol(vec![a], 0), false);
+        queue.give(Order::Patrol(vec![b], 0), true);
+        assert_eq!(queue.orders.len(), 1);
+        match queue.orders[0] {
+            Order::Patrol(ref waypoints, _) => assert_eq!(*waypoints, vec![a, b]),
+            _ => panic!("should be patrolling"),
+        }
+    }
+
+    #[test]
+    fn patrols_wrap_around() {
+        let (a, b) = (Position::new(0.0, 0.0), Position::new(0.5, 0.0));
+        let mut model = lone_ship_at(a);
+        model.give_order(Unit::Ship(0), Order::Patrol(vec![a, b], 0), false);
+        tick(&mut model);
+        assert_eq!(patrol_of(&model).1, 1);
+        assert!(model.is_travelling_to(Unit::Ship(0), b));
+
+        model.time = model.time + Duration::weeks(52);
+        tick(&mut model);
+        assert_eq!(patrol_of(&model), (vec![a, b], 0));
+        assert!(model.is_travelling_to(Unit::Ship(0), a));
+    }
+
+    #[test]
+    fn ships_stop_where_the_fuel_runs_out() {
+        let mut model = lone_ship_at(center);
+        model.ships.get_mut(&0).unwrap().fuel = 1.0;
+        model.give_order(Unit::Ship(0), Order::MoveTo(Position::new(10.0, 0.0)), false);
+        tick(&mut model);
+        let ship = &model.ships[&0];
+        assert_eq!(ship.fuel, 0.0);
+        match ship.movement {
+            Movement::Travel(_, _, to, _) => assert_eq!(to, Position::new(1.0, 0.0)),
+            _ => panic!("should be flying"),
+        }
+    }
+
+    #[test]
+    fn a_loop_with_nothing_to_do_goes_to_sleep() {
+        let mut model = lone_ship_at(center);
+        model.give_order(Unit::Ship(0), Order::MoveTo(center), false);
+        model.give_order(Unit::Ship(0), Order::Loop, true);
+        model.run_schedule();
+        // nothing in the queue makes it busy, so it isn't checked again
+        assert!(model.schedule.pop_due(model.time + Duration::weeks(1)).is_none());
+        assert_eq!(model.ships[&0].orders.remaining().len(), 2);
+    }
+
+    #[test]
+    fn escorts_keep_their_course_while_the_target_stays() {
+        let mut model = lone_ship_at(center);
+        let mut escorted = Ship::new(0, 0, sun);
+        let far = Position::new(2.0, 0.0);
+        escorted.movement = Movement::Travel(model.time, far, far, escorted.speed);
+        add_ship(&mut model, escorted);
+        model.give_order(Unit::Ship(0), Order::Escort(1), false);
+        tick(&mut model);
+        let planned = match model.ships[&0].movement {
+            Movement::Travel(start, _, to, _) => (start, to),
+            _ => panic!("should be flying"),
+        };
+        model.time = model.time + Duration::hours(1);
+        tick(&mut model);
+        match model.ships[&0].movement {
+            Movement::Travel(start, _, to, _) => assert_eq!((start, to), planned),
+            _ => panic!("should be flying"),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the worlds a game can start in. the window starts
+// in sol, the headless simulator can pick any of them by name or read one
+// from a file. a file has a line for every system and one for every body,
+// which goes in the system above it:
+//
+//     system 0 0
+//     star sun
+//     rocky earth 365 1.0 size=1 habitability=1 deposits=1,1,1 owner=0 people=7456000000
+//     rocky mars 780 1.52 size=0.28
+//     gas_giant jupiter 4333 5.2
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use chrono::Duration;
+use crate::geometry::{center, Position};
+use super::galaxy::{BodyClass, StellarBody, System};
+use super::colony::{Colony, Deposits, Population};
+use super::{GameModel, Rules};
+
+pub const scenario_names:[&str; 2] = ["sol", "earths"];
+
+pub fn by_name(name:&str, rules:Rules) -> Option<GameModel>{
+    let mut model = match name {
+        "sol" => sol(),
+        "earths" => earths(),
+        _ => return None
+    };
+    model.rules = rules;
+    Some(model)
+}
+
+// our own solar system with humanity still at home
+pub fn sol() -> GameModel{
+    let rocky = |size, habitability, deposits, name, days, distance| StellarBody::new(
+        BodyClass::Rocky(Colony::new_empty(size).with_resources(habitability, deposits)),
+        name,
+        Duration::days(days),
+        distance,
+    );
+    let gas_giant = |name, days, distance| StellarBody::new(
+        BodyClass::GasGiant,
+        name,
+        Duration::days(days),
+        distance,
+    );
+    GameModel::new(vec![System::new(center, vec![
+        StellarBody::create_single_star("sun"),
+        rocky(0.147, 0.0, Deposits::new(1.2, 0.4, 0.0), "mercury", 88, 0.387098),
+        rocky(0.902, 0.05, Deposits::new(0.9, 0.6, 0.2), "venus", 225, 0.723332),
+        StellarBody::new_earthlike("earth"),
+        rocky(0.284, 0.2, Deposits::new(0.8, 0.5, 0.1), "mars", 780, 1.523679),
+        gas_giant("jupiter", 4333, 5.20260),
+        gas_giant("saturn", 10759, 9.554909),
+        gas_giant("uranus", 30688, 19.2184),
+        gas_giant("neptune", 60182, 30.110387),
+    ])])
+}
+
+// four players with two earths each, of different sizes and crowdedness,
+// a rough field for comparing growth and income over long runs
+pub fn earths() -> GameModel{
+    let players = 4;
+    let systems = (0..players * 2).map(|i| {
+        let owner = i % players;
+        let size = 0.5 + (i / players) as f64;
+        let population = Population::new(1_000_000_000 * (i + 1) as i64);
+        System::new(Position::new(i as f64 * 5.0, 0.0), vec![
+            StellarBody::create_single_star("sun"),
+            StellarBody::new(
+                BodyClass::Rocky(Colony::new_inhabited(owner, size, population)
+                    .with_resources(1.0, Deposits::new(1.0, 1.0, 1.0))),
+                "earth",
+                Duration::days(365),
+                1.0,
+            ),
+        ])
+    }).collect();
+    GameModel::new(systems)
+}
+
+pub fn from_file(path:&Path, rules:Rules) -> Result<GameModel, String>{
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let mut model = parse(&text)?;
+    model.rules = rules;
+    Ok(model)
+}
+
+pub fn parse(text:&str) -> Result<GameModel, String>{
+    let mut systems:Vec<(Position, Vec<StellarBody>)> = Vec::new();
+    for (number, line) in text.lines().enumerate(){
+        let words:Vec<&str> = line.split_whitespace().collect();
+        let added = match words.split_first() {
+            None => continue,
+            Some((&"system", rest)) => parse_position(rest).map(|x| systems.push((x, Vec::new()))),
+            Some((kind, rest)) => match systems.last_mut() {
+                Some(system) => parse_body(kind, rest).map(|x| system.1.push(x)),
+                None => Err("a body has to come after its system".to_owned()),
+            },
+        };
+        added.map_err(|e| format!("line {}: {}", number + 1, e))?;
+    }
+    if systems.is_empty() {
+        return Err("the scenario has no systems".to_owned());
+    }
+    Ok(GameModel::new(systems.into_iter().map(|(position, bodies)| System::new(position, bodies)).collect()))
+}
+
+fn number<T:FromStr>(word:&str) -> Result<T, String>{
+    word.parse().map_err(|_| format!("{} is not a number", word))
+}
+
+fn parse_position(words:&[&str]) -> Result<Position, String>{
+    match *words {
+        [x, y] => Ok(Position::new(number(x)?, number(y)?)),
+        _ => Err("a system needs an x and a y".to_owned()),
+    }
+}
+
+fn parse_body(kind:&str, words:&[&str]) -> Result<StellarBody, String>{
+    if kind == "star" {
+        return match *words {
+            [name] => Ok(StellarBody::create_single_star(leak(name))),
+            _ => Err("a star only has a name".to_owned()),
+        };
+    }
+    let (name, orbit, distance, properties) = match *words {
+        [name, days, distance, ref properties @ ..] => (name, number(days)?, number(distance)?, properties),
+        _ => return Err(format!("a {} needs a name, orbit days and a distance", kind)),
+    };
+    let class = match kind {
+        "gas_giant" if properties.is_empty() => BodyClass::GasGiant,
+        "rocky" => BodyClass::Rocky(parse_colony(properties)?),
+        _ => return Err(format!("can't make a {} of {}", kind, properties.join(" "))),
+    };
+    Ok(StellarBody::new(class, leak(name), Duration::days(orbit), distance))
+}
+
+fn parse_colony(properties:&[&str]) -> Result<Colony, String>{
+    let mut size = 1.0;
+    let mut habitability = 0.0;
+    let mut deposits = Deposits::none();
+    let mut owner = None;
+    let mut people = None;
+    for property in properties{
+        let mut parts = property.splitn(2, '=');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return Err(format!("{} should look like name=value", property)),
+        };
+        match name {
+            "size" => size = number(value)?,
+            "habitability" => habitability = number(value)?,
+            "deposits" => deposits = match *value.split(',').collect::<Vec<_>>().as_slice() {
+                [metal, rare_earth, fuel] => Deposits::new(number(metal)?, number(rare_earth)?, number(fuel)?),
+                _ => return Err("deposits are metal,rare_earth,fuel".to_owned()),
+            },
+            "owner" => owner = Some(number(value)?),
+            "people" => people = Some(number(value)?),
+            _ => return Err(format!("a rocky body has no {}", name)),
+        }
+    }
+    let mut colony = match (owner, people) {
+        (Some(owner), Some(people)) => Colony::new_inhabited(owner, size, Population::new(people)),
+        (None, Some(_)) => return Err("people need an owner".to_owned()),
+        _ => Colony::new_empty(size),
+    };
+    colony.owner = owner;
+    Ok(colony.with_resources(habitability, deposits))
+}
+
+// bodies are named for the whole game, the built in ones are static
+fn leak(name:&str) -> &'static str{
+    Box::leak(name.to_owned().into_boxed_str())
+}
+
+// small worlds the tests of the other models start from
+#[cfg(test)]
+pub mod testing {
+    use crate::geometry::center;
+    use crate::model::galaxy::{BodyAddress, StellarBody, System};
+    use crate::model::ship::{Ship, ShipID};
+    use crate::model::{GameModel, Player};
+
+    pub const sun: BodyAddress = BodyAddress {
+        system_id: 0,
+        planet_id: 0,
+    };
+    pub const earth: BodyAddress = BodyAddress {
+        system_id: 0,
+        planet_id: 1,
+    };
+
+    // nothing but the sun, for ships to fly around in
+    pub fn sun_model() -> GameModel {
+        GameModel::new(vec![System::new(
+            center,
+            vec![StellarBody::create_single_star("sun")],
+        )])
+    }
+
+    // the sun and an inhabited earth of player 0
+    pub fn earth_model() -> GameModel {
+        GameModel::new(vec![System::new(
+            center,
+            vec![
+                StellarBody::create_single_star("sun"),
+                StellarBody::new_earthlike("earth"),
+            ],
+        )])
+    }
+
+    // gives the ship the next id, its owner joins the game if it wasn't in
+    pub fn add_ship(model: &mut GameModel, mut ship: Ship) -> ShipID {
+        while model.players.len() <= ship.owner {
+            let id = model.players.len();
+            model.players.push(Player::new(id));
+        }
+        ship.id = model.next_ship_id;
+        model.next_ship_id += 1;
+        model.ships.insert(ship.id, ship);
+        model.next_ship_id - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::scenario::*;
+
+    #[test]
+    fn every_scenario_has_a_colony_for_each_player() {
+        for name in scenario_names.iter() {
+            let model = by_name(name, Rules::new()).unwrap();
+            for player in model.players.iter() {
+                let owns_one = model
+                    .galaxy
+                    .systems
+                    .iter()
+                    .flat_map(|x| x.bodies.iter())
+                    .any(|x| x.get_colony().is_some_and(|c| c.owner == Some(player.id)));
+                assert!(owns_one, "{} has nothing for {}", name, player.id);
+            }
+        }
+        assert!(by_name("andromeda", Rules::new()).is_none());
+    }
+
+    #[test]
+    fn scenario_files_are_read() {
+        let model = parse(
+            "system 0 0\n\
+             star sun\n\
+             rocky earth 365 1.0 size=1 habitability=1 deposits=1,1,1 owner=1 people=1000\n\
+             \n\
+             system 5 -2\n\
+             rocky mars 780 1.52 size=0.28\n\
+             gas_giant jupiter 4333 5.2\n",
+        )
+        .unwrap();
+        assert_eq!(model.galaxy.systems.len(), 2);
+        assert_eq!(model.players.len(), 2);
+        assert_eq!(model.galaxy.systems[1].bodies[1].name, "jupiter");
+        let earth = model.galaxy.systems[0].bodies[1].get_colony().unwrap();
+        assert_eq!(earth.owner, Some(1));
+        assert_eq!(earth.population.as_ref().unwrap().head_count, 1000);
+        assert!(parse("star sun").is_err());
+        assert!(parse("system 0 0\nrocky venus 225 0.72 lava=1").is_err());
+    }
+}
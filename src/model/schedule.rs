@@ -0,0 +1,509 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains the scheduler. instead of updating every body every
+// step, colonies and units say when something is going to happen to them:
+// a construction being done, a battle being over, payday or a fleet
+// arriving. in between they're left alone, a colony is brought up to date
+// from its closed form models when its event comes up or when somebody
+// needs to look at it. people pay and grow all the time, worked out from
+// how they were at the start of the pay period, so it doesn't matter how
+// often that is. paydays counted from the start close the period. colonies
+// of different systems don't touch each other, so they're settled in
+// parallel.
+
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::collections::{BinaryHeap, BTreeMap, BTreeSet};
+use chrono::Duration;
+use rayon::prelude::*;
+use super::galaxy::{BodyAddress, BodyClass};
+use super::colony::{carrying_capacity_earth, AConstructable, Colony, Population};
+use super::accounting::Amount;
+use super::demography::Balance;
+use super::invasion::{battle_left, garrison_for};
+use super::order::Unit;
+use super::{GameModel, PlayerID};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Event{
+    // the colony has to be brought up to date
+    Colony(BodyAddress),
+    // the unit has to look at its orders again
+    Orders(Unit),
+}
+
+#[derive(Clone, Default)]
+pub struct Schedule{
+    queue:BinaryHeap<Reverse<(Duration, u64, Event)>>,
+    // events at the same time go in the order they were scheduled
+    next_sequence:u64,
+}
+impl Schedule{
+    pub fn new() -> Schedule{
+        Schedule::default()
+    }
+    pub fn at(&mut self, time:Duration, event:Event){
+        self.queue.push(Reverse((time, self.next_sequence, event)));
+        self.next_sequence += 1;
+    }
+    // the earliest event that's due by now
+    pub fn pop_due(&mut self, now:Duration) -> Option<(Duration, Event)>{
+        match self.queue.peek() {
+            Some(&Reverse((time, _, _))) if time <= now => {}
+            _ => return None
+        }
+        self.queue.pop().map(|Reverse((time, _, event))| (time, event))
+    }
+    pub fn len(&self) -> usize{
+        self.queue.len()
+    }
+    pub fn is_empty(&self) -> bool{
+        self.queue.is_empty()
+    }
+}
+
+// a populated colony settles its taxes and growth every time this passed
+fn payday() -> Duration{
+    Duration::weeks(1)
+}
+// paydays after the first time up to and including the second
+fn paydays_between(from:Duration, to:Duration) -> i64{
+    let period = payday().num_milliseconds();
+    to.num_milliseconds().div_euclid(period) - from.num_milliseconds().div_euclid(period)
+}
+// the first payday after the time
+fn next_payday(after:Duration) -> Duration{
+    let period = payday().num_milliseconds();
+    Duration::milliseconds((after.num_milliseconds().div_euclid(period) + 1) * period)
+}
+
+// the people as they were when the period started, untill the next payday
+// they're worked out from this. a new period starts early when the people,
+// their housing or their owner change in another way.
+#[derive(Clone)]
+pub struct PayPeriod{
+    start:Duration,
+    people:Population,
+    carrying_capacity:i64,
+    owner:Option<PlayerID>,
+    // tax since the start that went to the owner already
+    paid:Amount,
+}
+impl PayPeriod{
+    fn people_after(&self, duration:Duration, balance:&Balance) -> Population{
+        if duration <= Duration::zero() {
+            return self.people.clone();
+        }
+        let growth = self.people.calc_growth(self.carrying_capacity, duration, balance);
+        self.people.clone().grow(growth).age(duration, balance).calm_down(duration)
+    }
+}
+
+// pays the tax untill the given time and grows the people along
+fn accrue(colony:&mut Colony, carrying_capacity:i64, balance:&Balance, to:Duration, income:&mut Vec<(PlayerID, Amount)>){
+    let from = colony.settled_at;
+    let population = match colony.population {
+        Some(ref population) => population.clone(),
+        None => {
+            colony.pay_period = None;
+            return;
+        }
+    };
+    let unchanged = colony.pay_period.as_ref().is_some_and(|period| {
+        period.carrying_capacity == carrying_capacity && period.owner == colony.owner
+            && period.people_after(from - period.start, balance) == population
+    });
+    let mut period = match colony.pay_period.take() {
+        Some(period) if unchanged => period,
+        _ => PayPeriod{
+            start:from,
+            people:population,
+            carrying_capacity,
+            owner:colony.owner,
+            paid:Amount::zero(),
+        },
+    };
+    loop{
+        let payday = next_payday(period.start);
+        let end = payday.min(to);
+        let elapsed = end - period.start;
+        // from the start every time, so the parts add up to the whole
+        let tax = period.people.calc_tax_growing(carrying_capacity, elapsed, balance);
+        if let Some(owner) = period.owner {
+            income.push((owner, tax + -period.paid));
+        }
+        period.paid = tax;
+        let people = period.people_after(elapsed, balance);
+        if end == payday {
+            period = PayPeriod{start:payday, people:people.clone(), paid:Amount::zero(), ..period};
+        }
+        if end == to {
+            colony.population = Some(people);
+            break;
+        }
+    }
+    colony.pay_period = Some(period);
+}
+
+// how long untill something happens to the colony, None if nothing ever will
+fn colony_wait(colony:&Colony) -> Option<Duration>{
+    let mut waits = Vec::new();
+    if let Some(job) = colony.construction_queue.last() {
+        waits.push(job.remaining());
+    }
+    if let Some(ref battle) = colony.battle {
+        waits.push(battle_left(battle.troops, colony.garrison));
+    }
+    if colony.population.is_some() {
+        let period = payday().num_milliseconds();
+        waits.push(Duration::milliseconds(period - colony.settled_at.num_milliseconds().rem_euclid(period)));
+    }
+    // never in the past, that would settle it forever
+    waits.into_iter().min().map(|x| x.max(Duration::milliseconds(1)))
+}
+
+// whether settling would change more than the time it was settled at.
+// paydays are counted from the start, so skipping it changes nothing later
+fn outdated(colony:&Colony, now:Duration) -> bool{
+    let recruiting = colony.population.as_ref().is_some_and(|x| colony.garrison < garrison_for(x.head_count));
+    colony.settled_at < now && (
+        !colony.construction_queue.is_empty() || colony.battle.is_some() || recruiting
+        || colony.population.is_some() && paydays_between(colony.settled_at, now) > 0
+    )
+}
+
+// what settling a colony means for the rest of the model
+struct Settled{
+    income:Vec<(PlayerID, Amount)>,
+    completed:Vec<AConstructable>,
+}
+
+// brings the colony itself up to the given time
+fn settle(colony:&mut Colony, carrying_capacity:i64, balance:&Balance, now:Duration) -> Settled{
+    let elapsed = now - colony.settled_at;
+    let mut income = Vec::new();
+    if elapsed <= Duration::zero() {
+        colony.settled_at = now;
+        return Settled{income, completed:Vec::new()};
+    }
+    // a battle decided in between, the old owner is paid up to then
+    let decided = colony.battle.as_ref()
+        .map(|x| colony.settled_at + battle_left(x.troops, colony.garrison))
+        .filter(|x| *x < now);
+    if let Some(at) = decided {
+        accrue(colony, carrying_capacity, balance, at, &mut income);
+        colony.ground_tick(at - colony.settled_at);
+        colony.settled_at = at;
+    }
+    accrue(colony, carrying_capacity, balance, now, &mut income);
+    colony.ground_tick(now - colony.settled_at);
+    colony.settled_at = now;
+    Settled{
+        income,
+        completed:colony.construction_tick(elapsed),
+    }
+}
+
+impl GameModel{
+    pub fn carrying_capacity(&self, address:BodyAddress) -> i64{
+        let size = self.galaxy[address].get_colony().map_or(0.0, |x| x.size);
+        (size * carrying_capacity_earth + self.housing_at(address)) as i64
+    }
+    // everything that happened to the colony since it was last looked at,
+    // call this before changing a colony
+    pub fn settle_colony(&mut self, address:BodyAddress){
+        let capacity = self.carrying_capacity(address);
+        let now = self.time;
+        let balance = self.rules.balance;
+        let settled = match self.galaxy[address].class {
+            BodyClass::Rocky(ref mut colony) => settle(colony, capacity, &balance, now),
+            _ => return
+        };
+        self.finish_settling(address, settled);
+    }
+    // like settle_colony for each of them, the systems are done in parallel.
+    // nothing in one system changes another untill the merge afterwards,
+    // which goes in the given order, so it's the same as one by one.
+    pub fn settle_colonies(&mut self, addresses:&[BodyAddress]){
+        let capacities:Vec<i64> = addresses.iter().map(|x| self.carrying_capacity(*x)).collect();
+        let mut per_system:BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, address) in addresses.iter().enumerate(){
+            per_system.entry(address.system_id).or_default().push(i);
+        }
+        let now = self.time;
+        let balance = self.rules.balance;
+        let mut settled:Vec<(usize, Settled)> = self.galaxy.systems.par_iter_mut().enumerate()
+            // only what's settled gets copied away from older snapshots
+            .filter_map(|(system_id, system)| per_system.get(&system_id).map(move |jobs| (Arc::make_mut(system), jobs)))
+            .flat_map_iter(|(system, jobs)| {
+                let mut result = Vec::with_capacity(jobs.len());
+                for &i in jobs{
+                    let address = addresses[i];
+                    if let BodyClass::Rocky(ref mut colony) = system.bodies[address.planet_id].class {
+                        result.push((i, settle(colony, capacities[i], &balance, now)));
+                    }
+                }
+                result
+            })
+            .collect();
+        settled.sort_by_key(|x| x.0);
+        for (i, result) in settled{
+            self.finish_settling(addresses[i], result);
+        }
+    }
+    // whatever reaches outside the colony
+    fn finish_settling(&mut self, address:BodyAddress, settled:Settled){
+        for (owner, amount) in settled.income{
+            self.players[owner].earn(amount);
+        }
+        for job in settled.completed{
+            job.on_complete(self, &address);
+        }
+        self.schedule_colony(address);
+    }
+    pub fn settle_all(&mut self){
+        let addresses:Vec<BodyAddress> = self.galaxy.systems.iter()
+            .flat_map(|x| x.bodies.iter())
+            .filter(|x| x.get_colony().is_some())
+            .map(|x| x.address).collect();
+        self.settle_colonies(&addresses);
+    }
+    // like settle_all but only the colonies that would look different,
+    // the systems of the others stay shared with older snapshots
+    pub fn settle_outdated(&mut self){
+        let now = self.time;
+        let addresses:Vec<BodyAddress> = self.galaxy.systems.iter()
+            .flat_map(|x| x.bodies.iter())
+            .filter(|x| x.get_colony().is_some_and(|colony| outdated(colony, now)))
+            .map(|x| x.address).collect();
+        self.settle_colonies(&addresses);
+    }
+    // call this after the colony changed, an earlier event is forgotten
+    pub fn schedule_colony(&mut self, address:BodyAddress){
+        let due = match self.galaxy[address].get_colony() {
+            Some(colony) => colony_wait(colony).map(|x| colony.settled_at + x),
+            None => return
+        };
+        match self.galaxy[address].class {
+            BodyClass::Rocky(ref mut colony) if colony.due != due => colony.due = due,
+            _ => return
+        }
+        if let Some(time) = due {
+            self.schedule.at(time, Event::Colony(address));
+        }
+    }
+    // let the unit look at its orders on the next step
+    pub fn wake(&mut self, unit:Unit){
+        self.schedule.at(self.time, Event::Orders(unit));
+    }
+    // handles whatever is due by now
+    pub fn run_schedule(&mut self){
+        let mut colonies = Vec::new();
+        let mut units = BTreeSet::new();
+        while let Some((time, event)) = self.schedule.pop_due(self.time){
+            match event {
+                Event::Colony(address) => {
+                    // rescheduled since, this one is stale
+                    if self.galaxy[address].get_colony().and_then(|x| x.due) == Some(time) {
+                        colonies.push(address);
+                    }
+                }
+                Event::Orders(unit) => {
+                    units.insert(unit);
+                }
+            }
+        }
+        // settling reschedules into the future, so nothing new is due
+        self.settle_colonies(&colonies);
+        self.order_tick(units);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::colony::Population;
+    use crate::model::invasion::GroundBattle;
+    use crate::model::scenario::testing::{earth, earth_model};
+    use crate::model::galaxy::*;
+    use crate::model::*;
+    use chrono::Duration;
+
+    #[test]
+    fn settling_late_is_like_settling_often() {
+        let mut often = earth_model();
+        let mut late = earth_model();
+        for _ in 0..(52 * 3) {
+            often.time = often.time + Duration::weeks(1);
+            often.settle_colony(earth);
+        }
+        late.time = late.time + Duration::weeks(52 * 3);
+        late.settle_colony(earth);
+        let head_count = |model: &GameModel| {
+            model.galaxy[earth]
+                .get_colony()
+                .and_then(|x| x.population.as_ref())
+                .map_or(0, |x| x.head_count)
+        };
+        assert_eq!(head_count(&late), head_count(&often));
+        assert!(head_count(&late) > 7_456_000_000);
+        assert_eq!(late.players[0].money, often.players[0].money);
+        assert_eq!(
+            late.players[0].money_fraction,
+            often.players[0].money_fraction
+        );
+        assert!(late.players[0].money > 0);
+    }
+
+    #[test]
+    fn income_accrues_in_between_paydays() {
+        let end = Duration::weeks(20) + Duration::days(3);
+        let mut once = earth_model();
+        once.time = end;
+        once.settle_colony(earth);
+        let mut odd = earth_model();
+        while odd.time < end {
+            odd.time = (odd.time + Duration::hours(37)).min(end);
+            odd.settle_colony(earth);
+        }
+        let population = |model: &GameModel| {
+            model.galaxy[earth]
+                .get_colony()
+                .and_then(|x| x.population.as_ref())
+                .map(|x| (x.head_count, x.head_fraction))
+        };
+        assert_eq!(population(&once), population(&odd));
+        assert_eq!(once.players[0].money, odd.players[0].money);
+        assert_eq!(
+            once.players[0].money_fraction,
+            odd.players[0].money_fraction
+        );
+        // the three days after the last payday are paid too
+        let mut on_payday = earth_model();
+        on_payday.time = Duration::weeks(20);
+        on_payday.settle_colony(earth);
+        assert!(once.players[0].money > on_payday.players[0].money);
+    }
+
+    #[test]
+    fn captured_colony_pays_the_old_owner_untill_it_falls() {
+        let mut model = earth_model();
+        model.players.push(Player::new(1));
+        if let BodyClass::Rocky(ref mut colony) = model.galaxy.system_mut(0).bodies[1].class {
+            colony.battle = Some(GroundBattle {
+                attacker: 1,
+                troops: colony.garrison * 100.0,
+                started: Duration::zero(),
+            });
+        }
+        model.time = Duration::weeks(1);
+        model.settle_colony(earth);
+        let colony = model.galaxy[earth].get_colony().unwrap();
+        assert_eq!(colony.owner, Some(1));
+        // the battle takes days, both got part of the week
+        assert!(model.players[0].money > 0);
+        assert!(model.players[1].money > 0);
+        let mut peaceful = earth_model();
+        peaceful.time = Duration::weeks(1);
+        peaceful.settle_colony(earth);
+        assert!(model.players[0].money < peaceful.players[0].money);
+    }
+
+    #[test]
+    fn compliance_recovers_in_between() {
+        let conquered = Population::new(1000).conquered();
+        let year = Duration::weeks(52);
+        let mean = conquered.mean_compliance(year);
+        assert!(mean > conquered.compliance());
+        assert!(mean < conquered.clone().calm_down(year).compliance());
+        // sums up like the curve, not like the two ends
+        let midpoints: f64 = (0..52 * 24)
+            .map(|x| {
+                let halfway = Duration::weeks(x) / 24 + Duration::minutes(210);
+                conquered.clone().calm_down(halfway).compliance() / (52.0 * 24.0)
+            })
+            .sum();
+        assert!((mean - midpoints).abs() < 1e-6);
+        assert_eq!(Population::new(1000).mean_compliance(year), 1.0);
+    }
+
+    #[test]
+    fn idle_colonies_wait_for_payday() {
+        let mut model = earth_model();
+        // the payday of earth, nothing for the sun
+        assert_eq!(model.schedule.len(), 1);
+        model.time = Duration::days(6);
+        model.run_schedule();
+        assert_eq!(model.players[0].money, 0);
+        model.time = Duration::days(7);
+        model.run_schedule();
+        assert!(model.players[0].money > 0);
+        assert_eq!(model.schedule.len(), 1);
+    }
+
+    #[test]
+    fn parallel_settling_matches_serial() {
+        let systems = (0..8)
+            .map(|i| {
+                System::new(
+                    Position::new(i as f64, 0.0),
+                    vec![
+                        StellarBody::create_single_star("sun"),
+                        StellarBody::new_earthlike("earth"),
+                    ],
+                )
+            })
+            .collect();
+        let mut parallel = GameModel::new(systems);
+        // different sizes so every system grows differently
+        for i in 0..8 {
+            if let BodyClass::Rocky(ref mut colony) = parallel.galaxy.system_mut(i).bodies[1].class {
+                colony.size = 0.1 + i as f64 * 0.3;
+            }
+        }
+        let mut serial = parallel.clone();
+        let addresses: Vec<BodyAddress> = (0..8)
+            .map(|x| BodyAddress {
+                system_id: x,
+                planet_id: 1,
+            })
+            .collect();
+        for _ in 0..10 {
+            parallel.time = parallel.time + Duration::weeks(13);
+            parallel.settle_colonies(&addresses);
+            serial.time = serial.time + Duration::weeks(13);
+            for address in addresses.iter() {
+                serial.settle_colony(*address);
+            }
+        }
+        assert_eq!(parallel.players[0].money, serial.players[0].money);
+        assert_eq!(
+            parallel.players[0].money_fraction,
+            serial.players[0].money_fraction
+        );
+        for address in addresses {
+            let population = |model: &GameModel| {
+                model.galaxy[address]
+                    .get_colony()
+                    .and_then(|x| x.population.as_ref())
+                    .map(|x| (x.head_count, x.head_fraction))
+            };
+            assert_eq!(population(&parallel), population(&serial));
+        }
+    }
+}
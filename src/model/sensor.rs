@@ -0,0 +1,331 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models what players know about each other. ships and colonies
+// carry sensors, anything foreign in range becomes a contact. once out of
+// range the contact goes stale and we only remember where we last saw it.
+// the gui should only ever look at the model trough perceived_by. there is no
+// AI yet, when there is it has to do the same.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::{Au, BodyAddress, BodyClass};
+use super::colony::{Colony, Population};
+use super::ship::ShipID;
+use super::station::StationID;
+use super::order::OrderQueue;
+use super::ship::Movement;
+use super::light_lag::retarded_position;
+use super::{GameModel, PlayerID};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum ContactID{
+    Ship(ShipID),
+    Colony(BodyAddress),
+    Station(StationID),
+}
+
+#[derive(Clone)]
+pub struct Contact{
+    pub id:ContactID,
+    pub owner:Option<PlayerID>,
+    pub position:Position,
+    pub seen_at:Duration,
+    // in sensor range right now, otherwise this is old news
+    pub current:bool,
+    // only for colonies
+    pub head_count:Option<i64>,
+}
+
+pub const colony_sensor_range:Au = 2.0;
+
+// whether perceived_by has nothing left to hide of the colony
+fn shows_only_contact(colony:&Colony, player:PlayerID, contact:Option<&Contact>) -> bool{
+    colony.owner == Some(player) || (
+        colony.construction_queue.is_empty()
+        && colony.garrison == 0.0
+        && colony.battle.as_ref().is_none_or(|x| x.attacker == player)
+        && colony.owner == contact.and_then(|x| x.owner)
+        && colony.population == contact.and_then(|x| x.head_count).map(Population::new)
+    )
+}
+
+impl GameModel{
+    // every position with a range from which the player can see
+    pub fn sensor_sources(&self, player:PlayerID) -> Vec<(Position, Au)>{
+        let ships = self.ships.values().filter(|x| x.owner == player)
+            .map(|x| (x.movement.calc_position(&self.time, &self.galaxy), x.sensor_range()));
+        let colonies = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter(|x| x.get_colony().is_some_and(|colony| colony.owner == Some(player)))
+            .map(|x| (x.calc_position(&self.time), colony_sensor_range));
+        let stations = self.stations.values().filter(|x| x.owner == player)
+            .map(|x| (x.calc_position(&self.time, &self.galaxy), x.sensor_range()));
+        ships.chain(colonies).chain(stations).collect()
+    }
+    // the freshest image any source has of the target, with the light lag
+    // rule that's where the target was when the light left it
+    fn observe<F>(&self, sources:&[(Position, Au)], target_at:F) -> Option<(Position, Duration)>
+        where F: Fn(&Duration) -> Position {
+        sources.iter().filter_map(|source| {
+            let image = if self.rules.light_lag {
+                retarded_position(source.0, self.time, &target_at)
+            }else{
+                (target_at(&self.time), self.time)
+            };
+            if source.0.distance(image.0) <= source.1 {
+                Some(image)
+            }else{
+                None
+            }
+        }).max_by_key(|x| x.1)
+    }
+    // what the sources would pick up right now
+    fn detect(&self, player:PlayerID, sources:&[(Position, Au)]) -> Vec<Contact>{
+        let galaxy = &self.galaxy;
+        let ships = self.ships.values().filter(|x| x.owner != player).filter_map(|ship| {
+            let (position, seen_at) = self.observe(sources, |time| ship.movement.calc_position(time, galaxy))?;
+            Some(Contact{
+                id:ContactID::Ship(ship.id),
+                owner:Some(ship.owner),
+                position,
+                seen_at,
+                current:true,
+                head_count:None,
+            })
+        });
+        let colonies = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter()).filter_map(|body| {
+            let colony = body.get_colony()?;
+            if colony.owner == Some(player) || colony.owner.is_none() {
+                return None;
+            }
+            let (position, seen_at) = self.observe(sources, |time| body.calc_position(time))?;
+            Some(Contact{
+                id:ContactID::Colony(body.address),
+                owner:colony.owner,
+                position,
+                seen_at,
+                current:true,
+                head_count:colony.population.as_ref().map(|x| x.head_count),
+            })
+        });
+        let stations = self.stations.values().filter(|x| x.owner != player).filter_map(|station| {
+            let (position, seen_at) = self.observe(sources, |time| station.calc_position(time, galaxy))?;
+            Some(Contact{
+                id:ContactID::Station(station.id),
+                owner:Some(station.owner),
+                position,
+                seen_at,
+                current:true,
+                head_count:None,
+            })
+        });
+        ships.chain(colonies).chain(stations).collect()
+    }
+    // refresh the contact lists of all players
+    pub fn sensor_tick(&mut self){
+        for player in 0..self.players.len(){
+            let sources = self.sensor_sources(player);
+            let detected = self.detect(player, &sources);
+            let contacts = &mut self.players[player].contacts;
+            for contact in contacts.values_mut(){
+                contact.current = false;
+            }
+            for contact in detected{
+                contacts.insert(contact.id, contact);
+            }
+            // destroyed things are gone for good once we look at the spot
+            let ships = &self.ships;
+            let stations = &self.stations;
+            contacts.retain(|id, contact| {
+                let exists = match *id {
+                    ContactID::Ship(ship) => ships.contains_key(&ship),
+                    ContactID::Station(station) => stations.contains_key(&station),
+                    ContactID::Colony(_) => true,
+                };
+                contact.current || exists
+                    || !sources.iter().any(|source| source.0.distance(contact.position) <= source.1)
+            });
+        }
+    }
+    // the game as the player knows it, foreign ships out of sensor range
+    // are removed and foreign colonies show what we saw last.
+    // stale contacts are left in the player's contact list for drawing.
+    pub fn perceived_by(mut self, player:PlayerID) -> GameModel{
+        // colonies are only brought up to date when something happens,
+        // what's shown should be up to date anyway
+        self.settle_outdated();
+        let contacts = self.players[player].contacts.clone();
+        let is_current = |id:ContactID| contacts.get(&id).is_some_and(|x| x.current);
+        let light_lag = self.rules.light_lag;
+        self.ships = self.ships.into_iter()
+            .filter(|x| x.1.owner == player || is_current(ContactID::Ship(x.0)))
+            .map(|(id, mut ship)| {
+                if ship.owner != player {
+                    // we can see it, not what it's been told
+                    ship.fleet = None;
+                    ship.orders = OrderQueue::new();
+                    if light_lag {
+                        // the light of where it's now is still underway
+                        ship.movement = Movement::Fixed(contacts[&ContactID::Ship(id)].position);
+                    }
+                }
+                (id, ship)
+            }).collect();
+        self.fleets = self.fleets.into_iter().filter(|x| x.1.owner == player).collect();
+        self.stations = self.stations.into_iter()
+            .filter(|x| x.1.owner == player || is_current(ContactID::Station(x.0)))
+            .map(|(id, mut station)| {
+                if station.owner != player && light_lag {
+                    station.movement = Movement::Fixed(contacts[&ContactID::Station(id)].position);
+                }
+                (id, station)
+            }).collect();
+        for system in self.galaxy.systems.iter_mut(){
+            // only systems with something to hide are copied
+            let hidden = system.bodies.iter().all(|body| match body.class {
+                BodyClass::Rocky(ref colony) => shows_only_contact(colony, player, contacts.get(&ContactID::Colony(body.address))),
+                _ => true
+            });
+            if hidden {
+                continue;
+            }
+            for body in Arc::make_mut(system).bodies.iter_mut(){
+                let address = body.address;
+                if let BodyClass::Rocky(ref mut colony) = body.class {
+                    if colony.owner == Some(player){
+                        continue;
+                    }
+                    colony.construction_queue.clear();
+                    colony.garrison = 0.0;
+                    if colony.battle.as_ref().is_some_and(|x| x.attacker != player) {
+                        colony.battle = None;
+                    }
+                    match contacts.get(&ContactID::Colony(address)) {
+                        Some(contact) => {
+                            colony.owner = contact.owner;
+                            colony.population = contact.head_count.map(Population::new);
+                        }
+                        None => {
+                            colony.owner = None;
+                            colony.population = None;
+                        }
+                    }
+                }
+            }
+        }
+        // mines don't show up as contacts, only while we're looking at them
+        let sources = self.sensor_sources(player);
+        let (time, galaxy) = (self.time, &self.galaxy);
+        self.minefields.retain(|_, field| field.owner == player || {
+            let position = field.calc_position(&time, galaxy);
+            sources.iter().any(|x| x.0.distance(position) <= x.1)
+        });
+        self.missiles.retain(|_, missile| missile.owner == player
+            || sources.iter().any(|x| x.0.distance(missile.position) <= x.1));
+        self.hide_unsurveyed(player);
+        for other in self.players.iter_mut().filter(|x| x.id != player){
+            other.money = 0;
+            other.selected.clear();
+            other.contacts.clear();
+            other.surveyed.clear();
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::{center, Position};
+    use crate::model::colony::Colony;
+    use crate::model::galaxy::*;
+    use crate::model::sensor::ContactID;
+    use crate::model::scenario::testing::{add_ship, sun, sun_model};
+    use crate::model::ship::*;
+    use crate::model::*;
+    use chrono::Duration;
+    use std::sync::Arc;
+
+    fn two_player_model() -> GameModel {
+        let mut model = sun_model();
+        for owner in 0..2 {
+            add_ship(&mut model, Ship::new(owner, 0, sun));
+        }
+        model
+    }
+
+    #[test]
+    fn contacts_go_stale_out_of_range() {
+        let mut model = two_player_model();
+        model.sensor_tick();
+        assert!(model.players[0].contacts[&ContactID::Ship(1)].current);
+
+        let far_away = Position::new(100.0, 0.0);
+        model.ships.get_mut(&1).unwrap().movement =
+            Movement::Travel(model.time, far_away, far_away, 0.0);
+        model.time = model.time + Duration::days(1);
+        model.sensor_tick();
+        let contact = model.players[0].contacts[&ContactID::Ship(1)].clone();
+        assert!(!contact.current);
+        assert_ne!(contact.position, far_away);
+
+        let perceived = model.perceived_by(0);
+        assert!(perceived.ships.contains_key(&0));
+        assert!(!perceived.ships.contains_key(&1));
+    }
+
+    #[test]
+    fn systems_without_secrets_stay_shared() {
+        let rock = StellarBody::new(
+            BodyClass::Rocky(Colony::new_empty(0.5)),
+            "rock",
+            Duration::days(300),
+            50.0,
+        );
+        let mut model = GameModel::new(vec![
+            System::new(
+                center,
+                vec![
+                    StellarBody::create_single_star("sun"),
+                    StellarBody::new_earthlike("earth"),
+                ],
+            ),
+            System::new(
+                Position::new(100.0, 0.0),
+                vec![StellarBody::create_single_star("empty")],
+            ),
+            System::new(
+                Position::new(200.0, 0.0),
+                vec![StellarBody::create_single_star("far"), rock],
+            ),
+        ]);
+        // in between paydays, as the writer would publish it
+        model.time = model.time + Duration::days(3);
+        let snapshot = Arc::new(model);
+        let perceived = (*snapshot).clone().perceived_by(0);
+        let shared = |system: usize| {
+            Arc::ptr_eq(
+                &snapshot.galaxy.systems[system],
+                &perceived.galaxy.systems[system],
+            )
+        };
+        assert!(shared(0));
+        assert!(shared(1));
+        // the rock isn't surveyed yet
+        assert!(!shared(2));
+    }
+}
@@ -21,6 +21,8 @@ use crate::geometry::Position;
 use super::galaxy::*;
 use super::colony::Constructable;
 use super::{GameModel, PlayerID};
+use super::fleet::FleetID;
+use super::order::OrderQueue;
 use chrono::Duration;
 pub type ShipID = usize;
 #[derive(Clone)]
@@ -29,6 +31,15 @@ pub struct Ship{
     pub id:ShipID,
     ship_price:i64,
     pub movement:Movement,
+    pub speed:Au, // au/s
+    pub fleet:Option<FleetID>,
+    pub orders:OrderQueue,
+    // fuel is measured in how many au we can still fly
+    pub fuel:Au,
+    pub fuel_capacity:Au,
+    pub components:Vec<Component>,
+    // damage it can take before it's destroyed
+    pub hull:f64,
 }
 impl Ship{
     pub fn new(
@@ -42,26 +53,150 @@ impl Ship{
             id:MAX, // make sure it'll crash if not assigned
             ship_price:ship_price,
             movement:Movement::Orbit(Duration::zero(), construct_location),
+            speed:ship_speed,
+            fleet:None,
+            orders:OrderQueue::new(),
+            fuel:ship_fuel,
+            fuel_capacity:ship_fuel,
+            components:vec![Component::Sensor(ship_sensor_range)],
+            hull:ship_hull,
         }
     }
+    pub fn with_component(mut self, component:Component) -> Ship{
+        self.components.push(component);
+        self
+    }
+    pub fn can_survey(&self) -> bool{
+        self.components.contains(&Component::Survey)
+    }
+    // the best sensor decides how far we can see
+    pub fn sensor_range(&self) -> Au{
+        self.components.iter().fold(0.0, |prev, component| match component {
+            &Component::Sensor(range) if range > prev => range,
+            _ => prev
+        })
+    }
+    // fly in a straight line from wherever we are now, as far as the fuel
+    // allows us to go
+    pub fn move_to(&mut self, time:Duration, galaxy:&Galaxy, destination:Position, speed:Au){
+        let from = self.movement.calc_position(&time, galaxy);
+        // we paid for the entire previous trip, get back what we didn't fly
+        if let Movement::Travel(_, _, to, _) = self.movement {
+            self.fuel = (self.fuel + from.distance(to)).min(self.fuel_capacity);
+        }
+        let distance = from.distance(destination);
+        let reachable = if distance > self.fuel {
+            from.lerp(destination, self.fuel / distance)
+        }else{
+            destination
+        };
+        self.fuel -= from.distance(reachable);
+        self.movement = Movement::Travel(time, from, reachable, speed);
+    }
+    pub fn enter_orbit(&mut self, time:Duration, galaxy:&Galaxy, address:BodyAddress){
+        if let Movement::Travel(_, _, to, _) = self.movement {
+            // close enough, we never flew the last bit
+            let here = self.movement.calc_position(&time, galaxy);
+            self.fuel = (self.fuel + here.distance(to)).min(self.fuel_capacity);
+        }
+        self.movement = Movement::Orbit(time, address);
+    }
+}
+// about 15 km/s
+const ship_speed:Au = 0.000_000_1;
+const ship_fuel:Au = 100.0;
+const ship_sensor_range:Au = 0.5;
+const ship_hull:f64 = 100.0;
+
+// the things a ship can be fitted with
+#[derive(Clone, PartialEq)]
+pub enum Component{
+    // detection range in au
+    Sensor(Au),
+    // reveals what's on a body when close enough
+    Survey,
+    // soldiers aboard, to be landed on a colony
+    Troops(f64),
+    // hull damage per hour to whatever we're ordered to attack
+    Cannon(f64),
+    // mines aboard, dropped with the lay mines order
+    MineLayer(f64),
+    // enemy mines cleared per hour while inside their field
+    MineSweeper(f64),
+    // missiles left to fire
+    MissileLauncher(f64),
+    // chance to shoot down a missile in range, per missile step
+    PointDefence(f64),
 }
 impl Constructable for Ship{
     fn on_complete(&self, model:&mut GameModel, address:&BodyAddress)->(){
-        print!("completed");
         let mut result = self.clone();
-        result.id = model.ships.len();
+        result.id = model.next_ship_id;
+        model.next_ship_id += 1;
         result.movement = Movement::Orbit(model.time, *address);
-        model.ships.push(result);
+        model.ships.insert(result.id, result);
     }
     fn price(&self) -> i64{
         return self.ship_price;
     }
+    fn journal(&self) -> String{
+        let components:String = self.components.iter().map(|x| format!(" {}", x.journal())).collect();
+        format!("ship {} {}{}", self.owner, self.ship_price, components)
+    }
+}
+impl Ship{
+    // the ship as it was journaled, to be build at the address
+    pub fn from_journal(words:&[&str], address:BodyAddress) -> Option<Ship>{
+        let owner = words.first()?.parse().ok()?;
+        let price = words.get(1)?.parse().ok()?;
+        let mut ship = Ship::new(owner, price, address);
+        ship.components = words[2..].iter().map(|x| Component::from_journal(x)).collect::<Option<_>>()?;
+        Some(ship)
+    }
+}
+impl Component{
+    pub fn journal(&self) -> String{
+        match *self {
+            Component::Sensor(range) => format!("sensor={}", range),
+            Component::Survey => "survey".to_owned(),
+            Component::Troops(troops) => format!("troops={}", troops),
+            Component::Cannon(damage) => format!("cannon={}", damage),
+            Component::MineLayer(mines) => format!("mine_layer={}", mines),
+            Component::MineSweeper(swept) => format!("mine_sweeper={}", swept),
+            Component::MissileLauncher(missiles) => format!("missile_launcher={}", missiles),
+            Component::PointDefence(chance) => format!("point_defence={}", chance),
+        }
+    }
+    pub fn from_journal(word:&str) -> Option<Component>{
+        if word == "survey" {
+            return Some(Component::Survey);
+        }
+        let mut parts = word.splitn(2, '=');
+        let name = parts.next()?;
+        let value:f64 = parts.next()?.parse().ok()?;
+        match name {
+            "sensor" => Some(Component::Sensor(value)),
+            "troops" => Some(Component::Troops(value)),
+            "cannon" => Some(Component::Cannon(value)),
+            "mine_layer" => Some(Component::MineLayer(value)),
+            "mine_sweeper" => Some(Component::MineSweeper(value)),
+            "missile_launcher" => Some(Component::MissileLauncher(value)),
+            "point_defence" => Some(Component::PointDefence(value)),
+            _ => None
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum Movement{
     Vector(Duration, Position, Velocity),
-    Orbit(Duration, BodyAddress)
+    Orbit(Duration, BodyAddress),
+    // start time, from, to, speed in au/s, stops once arrived
+    Travel(Duration, Position, Position, Au),
+    // doesn't move, used for what sensors saw some time ago
+    Fixed(Position),
+    // sits 60 degrees ahead or behind the body in its orbit
+    Lagrange(BodyAddress, LagrangePoint),
 }
 impl Movement{
     pub fn calc_position(&self, time:&Duration, galaxy:&Galaxy)->Position{
@@ -75,9 +210,58 @@ impl Movement{
                     &(*time - start_time),
                 )
             }
+            &Movement::Travel(start_time, from, to, speed) => {
+                let distance = from.distance(to);
+                let seconds = (*time - start_time).num_milliseconds() as f64 / 1000.0;
+                if distance <= 0.0 || speed * seconds >= distance {
+                    return to;
+                }
+                from.lerp(to, (speed * seconds) / distance)
+            }
+            &Movement::Fixed(position) => position,
+            &Movement::Lagrange(address, point) => point.calc_position(galaxy[address].calc_position(time)),
+        }
+    }
+    // the body we move along with, if any
+    pub fn anchored_to(&self) -> Option<BodyAddress>{
+        match self {
+            &Movement::Orbit(_, address) | &Movement::Lagrange(address, _) => Some(address),
+            _ => None
+        }
+    }
+    pub fn arrival_time(&self) -> Option<Duration>{
+        match self {
+            &Movement::Travel(start_time, from, to, speed) if speed > 0.0 => {
+                let seconds = from.distance(to) / speed;
+                Some(start_time + Duration::milliseconds((seconds * 1000.0).ceil() as i64))
+            }
+            _ => None
         }
     }
 }
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LagrangePoint{
+    // leading
+    L4,
+    // trailing
+    L5,
+}
+impl LagrangePoint{
+    // bodies orbit the origin, so this is the body's position rotated
+    pub fn calc_position(&self, body:Position) -> Position{
+        use std::f64::consts;
+        let angle = match *self {
+            LagrangePoint::L4 => consts::PI / 3.0,
+            LagrangePoint::L5 => -consts::PI / 3.0,
+        };
+        // orbits go clockwise, see calc_orbit
+        Position::new(
+            body.x * angle.cos() + body.y * angle.sin(),
+            body.y * angle.cos() - body.x * angle.sin()
+        )
+    }
+}
+
 const ship_orbit_distance:Au = 0.000_000_000_668_449_198;
 
 #[derive(Clone)]
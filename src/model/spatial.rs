@@ -0,0 +1,127 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file contains a uniform grid to quickly find what's near a position,
+// it gets rebuild every tick from whatever calc_position says. the cells are
+// kept in order so a query gives the same order every run, replays need that.
+
+use std::collections::BTreeMap;
+use crate::geometry::Position;
+use super::galaxy::Au;
+
+pub struct SpatialGrid<T>{
+    cell_size:Au,
+    cells:BTreeMap<(i64, i64), Vec<(Position, T)>>,
+    count:usize,
+}
+impl<T:Clone> SpatialGrid<T>{
+    pub fn new(cell_size:Au) -> SpatialGrid<T>{
+        SpatialGrid{
+            cell_size,
+            cells:BTreeMap::new(),
+            count:0,
+        }
+    }
+    fn cell_of(&self, position:Position) -> (i64, i64){
+        (
+            (position.x / self.cell_size).floor() as i64,
+            (position.y / self.cell_size).floor() as i64
+        )
+    }
+    pub fn insert(&mut self, position:Position, item:T){
+        let cell = self.cell_of(position);
+        self.cells.entry(cell).or_default().push((position, item));
+        self.count += 1;
+    }
+    // everything within the radius of the center
+    pub fn query(&self, center:Position, radius:Au) -> Vec<(Position, T)>{
+        let low = self.cell_of(Position::new(center.x - radius, center.y - radius));
+        let high = self.cell_of(Position::new(center.x + radius, center.y + radius));
+        let within = |x:&&(Position, T)| x.0.distance(center) <= radius;
+        let cell_count = (high.0 - low.0 + 1) as f64 * (high.1 - low.1 + 1) as f64;
+        // a huge area is faster to just scan
+        if cell_count > self.count as f64 {
+            return self.cells.values().flat_map(|x| x.iter()).filter(within).cloned().collect();
+        }
+        let mut result = Vec::new();
+        for x in low.0..(high.0 + 1){
+            for y in low.1..(high.1 + 1){
+                if let Some(items) = self.cells.get(&(x, y)){
+                    result.extend(items.iter().filter(within).cloned());
+                }
+            }
+        }
+        result
+    }
+}
+
+// how much of the line from start to end lies within the circle
+pub fn chord_length(start:Position, end:Position, center:Position, radius:Au) -> Au{
+    let dx = end.x - start.x;
+    let dy = end.y - start.y;
+    let fx = start.x - center.x;
+    let fy = start.y - center.y;
+    let a = dx * dx + dy * dy;
+    if a <= 0.0 {
+        return 0.0;
+    }
+    let b = 2.0 * (fx * dx + fy * dy);
+    let c = fx * fx + fy * fy - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant <= 0.0 {
+        return 0.0;
+    }
+    let root = discriminant.sqrt();
+    let enter = ((-b - root) / (2.0 * a)).max(0.0);
+    let exit = ((-b + root) / (2.0 * a)).min(1.0);
+    if exit <= enter {
+        return 0.0;
+    }
+    (exit - enter) * a.sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::spatial::*;
+
+    #[test]
+    fn query_finds_only_what_is_near() {
+        let mut grid = SpatialGrid::new(1.0);
+        grid.insert(Position::new(0.5, 0.5), 1);
+        grid.insert(Position::new(-3.0, 2.0), 2);
+        grid.insert(Position::new(10.0, 10.0), 3);
+        let mut found: Vec<i32> = grid
+            .query(Position::new(0.0, 0.0), 4.0)
+            .into_iter()
+            .map(|x| x.1)
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+    }
+
+    #[test]
+    fn chord_through_the_middle() {
+        let center = Position::new(0.0, 0.0);
+        let through = chord_length(Position::new(-2.0, 0.0), Position::new(2.0, 0.0), center, 1.0);
+        assert!((through - 2.0).abs() < 1e-9);
+        let halfway = chord_length(Position::new(-2.0, 0.0), Position::new(0.0, 0.0), center, 1.0);
+        assert!((halfway - 1.0).abs() < 1e-9);
+        let miss = chord_length(Position::new(-2.0, 2.0), Position::new(2.0, 2.0), center, 1.0);
+        assert_eq!(miss, 0.0);
+    }
+}
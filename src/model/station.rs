@@ -0,0 +1,198 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models orbital structures, the houses of age of empires.
+// they can't fly, they orbit the body they were build at or sit in one of
+// its lagrange points, and move along with it.
+
+use chrono::Duration;
+use crate::geometry::Position;
+use super::galaxy::{Au, BodyAddress, Galaxy};
+use super::colony::Constructable;
+use super::ship::{LagrangePoint, Movement};
+use super::{GameModel, PlayerID};
+
+pub type StationID = usize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StationKind{
+    // room for more people on the body below
+    Habitat,
+    // sees far and passes on orders
+    CommSatellite,
+    // refuels ships that come close
+    Depot,
+}
+
+// where the station will be put once build
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Anchor{
+    Orbit,
+    Lagrange(LagrangePoint),
+}
+
+#[derive(Clone)]
+pub struct Station{
+    pub owner:PlayerID,
+    pub id:StationID,
+    pub kind:StationKind,
+    pub anchor:Anchor,
+    pub movement:Movement,
+    pub hull:f64,
+    price:i64,
+}
+impl Station{
+    pub fn new(owner:PlayerID, price:i64, kind:StationKind, anchor:Anchor, construct_location:BodyAddress) -> Station{
+        Station{
+            owner,
+            id:usize::MAX, // make sure it'll crash if not assigned
+            kind,
+            anchor,
+            movement:Station::anchor_movement(anchor, Duration::zero(), construct_location),
+            hull:station_hull,
+            price,
+        }
+    }
+    fn anchor_movement(anchor:Anchor, time:Duration, address:BodyAddress) -> Movement{
+        match anchor {
+            Anchor::Orbit => Movement::Orbit(time, address),
+            Anchor::Lagrange(point) => Movement::Lagrange(address, point),
+        }
+    }
+    pub fn calc_position(&self, time:&Duration, galaxy:&Galaxy) -> Position{
+        self.movement.calc_position(time, galaxy)
+    }
+    pub fn sensor_range(&self) -> Au{
+        match self.kind {
+            StationKind::CommSatellite => comm_sensor_range,
+            _ => station_sensor_range,
+        }
+    }
+    // people that can live on a habitat
+    pub fn housing(&self) -> f64{
+        match self.kind {
+            StationKind::Habitat => habitat_housing,
+            _ => 0.0,
+        }
+    }
+    pub fn is_relay(&self) -> bool{
+        self.kind == StationKind::CommSatellite
+    }
+}
+const station_hull:f64 = 500.0;
+const station_sensor_range:Au = 0.5;
+const comm_sensor_range:Au = 3.0;
+const habitat_housing:f64 = 1_000_000_000.0;
+// depots refuel ships this close
+pub const supply_range:Au = 0.01;
+
+impl Constructable for Station{
+    fn on_complete(&self, model:&mut GameModel, address:&BodyAddress){
+        let mut result = self.clone();
+        result.id = model.next_station_id;
+        model.next_station_id += 1;
+        result.movement = Station::anchor_movement(self.anchor, model.time, *address);
+        model.settle_colony(*address);
+        model.stations.insert(result.id, result);
+        model.schedule_colony(*address);
+    }
+    fn work_needed(&self) -> Duration{Duration::weeks(12)}
+    fn price(&self) -> i64{
+        self.price
+    }
+    fn journal(&self) -> String{
+        let kind = match self.kind {
+            StationKind::Habitat => "habitat",
+            StationKind::CommSatellite => "comm_satellite",
+            StationKind::Depot => "depot",
+        };
+        let anchor = match self.anchor {
+            Anchor::Orbit => "orbit",
+            Anchor::Lagrange(LagrangePoint::L4) => "l4",
+            Anchor::Lagrange(LagrangePoint::L5) => "l5",
+        };
+        format!("station {} {} {} {}", self.owner, self.price, kind, anchor)
+    }
+}
+impl Station{
+    // the station as it was journaled, to be build at the address
+    pub fn from_journal(words:&[&str], address:BodyAddress) -> Option<Station>{
+        if words.len() != 4 {
+            return None;
+        }
+        let kind = match words[2] {
+            "habitat" => StationKind::Habitat,
+            "comm_satellite" => StationKind::CommSatellite,
+            "depot" => StationKind::Depot,
+            _ => return None
+        };
+        let anchor = match words[3] {
+            "orbit" => Anchor::Orbit,
+            "l4" => Anchor::Lagrange(LagrangePoint::L4),
+            "l5" => Anchor::Lagrange(LagrangePoint::L5),
+            _ => return None
+        };
+        Some(Station::new(words[0].parse().ok()?, words[1].parse().ok()?, kind, anchor, address))
+    }
+}
+
+impl GameModel{
+    // habitats add to what the body below can carry
+    pub fn housing_at(&self, address:BodyAddress) -> f64{
+        let owner = self.galaxy[address].get_colony().and_then(|x| x.owner);
+        self.stations.values()
+            .filter(|x| Some(x.owner) == owner && x.movement.anchored_to() == Some(address))
+            .map(|x| x.housing())
+            .sum()
+    }
+    // ships near a friendly depot get their tanks filled
+    pub fn supply_tick(&mut self){
+        let depots:Vec<(PlayerID, Position)> = self.stations.values()
+            .filter(|x| x.kind == StationKind::Depot)
+            .map(|x| (x.owner, x.calc_position(&self.time, &self.galaxy)))
+            .collect();
+        let time = self.time;
+        let galaxy = &self.galaxy;
+        for ship in self.ships.values_mut(){
+            let position = ship.movement.calc_position(&time, galaxy);
+            if depots.iter().any(|x| x.0 == ship.owner && x.1.distance(position) <= supply_range){
+                ship.fuel = ship.fuel_capacity;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Position;
+    use crate::model::ship::LagrangePoint;
+
+    #[test]
+    fn lagrange_points_share_the_orbit() {
+        let body = Position::new(0.0, 1.0);
+        let leading = LagrangePoint::L4.calc_position(body);
+        let trailing = LagrangePoint::L5.calc_position(body);
+        for point in [leading, trailing].iter() {
+            assert!((point.distance(Position::new(0.0, 0.0)) - 1.0).abs() < 1e-9);
+            // an equilateral triangle with the star
+            assert!((point.distance(body) - 1.0).abs() < 1e-9);
+        }
+        // orbits go clockwise so leading is to the right of the top
+        assert!(leading.x > 0.0);
+        assert!(trailing.x < 0.0);
+    }
+}
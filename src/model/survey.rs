@@ -0,0 +1,162 @@
+// This program is a 4x space game.
+// Copyright (C) 2016 Jappie Klooster
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.If not, see <http://www.gnu.org/licenses/>.
+
+
+// this file models exploration. you can see a planet from afar, but what's
+// on it stays a mystery untill a survey ship pays a visit or it comes close
+// enough to one of your sensors to be resolved.
+
+use crate::geometry::Position;
+use super::galaxy::{Au, BodyAddress, BodyClass};
+use super::colony::Deposits;
+use super::sensor::colony_sensor_range;
+use super::{GameModel, PlayerID};
+use std::sync::Arc;
+
+// a survey ship has to get this close to do its job
+pub const survey_distance:Au = 0.01;
+// sensors resolve details at this fraction of their range
+const sensor_resolve_fraction:f64 = 0.1;
+
+impl GameModel{
+    // things that can resolve body details, position and range
+    fn surveyors(&self, player:PlayerID) -> Vec<(Position, Au)>{
+        let ships = self.ships.values().filter(|x| x.owner == player).map(|ship| {
+            let range = ship.sensor_range() * sensor_resolve_fraction;
+            let range = if ship.can_survey() && survey_distance > range {
+                survey_distance
+            }else{
+                range
+            };
+            (ship.movement.calc_position(&self.time, &self.galaxy), range)
+        });
+        let colonies = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter(|x| x.get_colony().is_some_and(|colony| colony.owner == Some(player)))
+            .map(|x| (x.calc_position(&self.time), colony_sensor_range * sensor_resolve_fraction));
+        ships.chain(colonies).collect()
+    }
+    pub fn survey_tick(&mut self){
+        for player in 0..self.players.len(){
+            let surveyors = self.surveyors(player);
+            let resolved:Vec<BodyAddress> = self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+                .filter(|body| !self.players[player].surveyed.contains(&body.address))
+                .filter(|body| {
+                    let position = body.calc_position(&self.time);
+                    surveyors.iter().any(|x| x.0.distance(position) <= x.1)
+                })
+                .map(|x| x.address).collect();
+            self.players[player].surveyed.extend(resolved);
+        }
+    }
+    pub fn is_surveyed(&self, player:PlayerID, address:BodyAddress) -> bool{
+        self.players[player].surveyed.contains(&address)
+    }
+    // where auto explore goes next, stars have nothing to find
+    pub fn nearest_unsurveyed(&self, player:PlayerID, from:Position) -> Option<BodyAddress>{
+        self.galaxy.systems.iter().flat_map(|x| x.bodies.iter())
+            .filter(|x| !matches!(x.class, BodyClass::Star))
+            .filter(|x| !self.is_surveyed(player, x.address))
+            .map(|x| (x.address, x.calc_position(&self.time).distance(from)))
+            .fold(None, |prev:Option<(BodyAddress, Au)>, cur| match prev {
+                Some(best) if best.1 <= cur.1 => Some(best),
+                _ => Some(cur)
+            })
+            .map(|x| x.0)
+    }
+    // forget what the player hasn't surveyed, part of perceived_by
+    pub fn hide_unsurveyed(&mut self, player:PlayerID){
+        let surveyed = self.players[player].surveyed.clone();
+        for system in self.galaxy.systems.iter_mut(){
+            // only systems with something to hide are copied
+            let hidden = system.bodies.iter().all(|body| surveyed.contains(&body.address) || match body.class {
+                BodyClass::Rocky(ref colony) => colony.size == 0.0 && colony.habitability == 0.0
+                    && colony.deposits == Deposits::none(),
+                _ => true
+            });
+            if hidden {
+                continue;
+            }
+            for body in Arc::make_mut(system).bodies.iter_mut(){
+                if surveyed.contains(&body.address){
+                    continue;
+                }
+                if let BodyClass::Rocky(ref mut colony) = body.class {
+                    colony.size = 0.0;
+                    colony.habitability = 0.0;
+                    colony.deposits = Deposits::none();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::galaxy::*;
+    use crate::model::scenario::{self, testing::add_ship};
+    use crate::model::ship::*;
+    use crate::model::*;
+
+    fn body(planet_id: usize) -> BodyAddress {
+        BodyAddress {
+            system_id: 0,
+            planet_id,
+        }
+    }
+    // sol with a second player that only has a survey ship next to mars
+    fn surveying_mars() -> GameModel {
+        let mut model = scenario::sol();
+        let mars = model.galaxy[body(4)].calc_position(&model.time);
+        let mut ship = Ship::new(1, 0, body(4)).with_component(Component::Survey);
+        ship.movement = Movement::Travel(model.time, mars, mars, 0.0);
+        add_ship(&mut model, ship);
+        model
+    }
+
+    #[test]
+    fn survey_ships_survey_what_they_visit() {
+        let mut model = surveying_mars();
+        let mars = model.galaxy[body(4)].calc_position(&model.time);
+        assert_eq!(model.nearest_unsurveyed(1, mars), Some(body(4)));
+        model.survey_tick();
+        assert!(model.is_surveyed(1, body(4)));
+        assert!(!model.is_surveyed(1, body(3)));
+        // explore goes on to the next one
+        assert_ne!(model.nearest_unsurveyed(1, mars), Some(body(4)));
+        // earth sees itself
+        assert!(model.is_surveyed(0, body(3)));
+    }
+
+    #[test]
+    fn only_unsurveyed_bodies_are_hidden() {
+        let mut model = surveying_mars();
+        model.survey_tick();
+        let seen = model.clone();
+        model.hide_unsurveyed(1);
+        let rocky = |model: &GameModel, address| match model.galaxy[address].class {
+            BodyClass::Rocky(ref colony) => (colony.size, colony.habitability, colony.deposits.metal),
+            _ => panic!("should be rocky"),
+        };
+        assert_eq!(rocky(&model, body(4)), rocky(&seen, body(4)));
+        assert!(rocky(&seen, body(3)).0 > 0.0);
+        for address in [body(1), body(2), body(3)].iter() {
+            assert_eq!(rocky(&model, *address), (0.0, 0.0, 0.0));
+            let colony = model.galaxy[*address].get_colony().unwrap();
+            assert_eq!(colony.deposits.rare_earth, 0.0);
+            assert_eq!(colony.deposits.fuel, 0.0);
+        }
+    }
+}
@@ -22,7 +22,9 @@ use conrod::{widget_ids, color, widget, Colorable, Labelable, Positionable, Size
 use conrod;
 
 use super::state_machine::{State, StateChange};
-use super::conquest::ConquestState;
+use super::conquest::{last_journal, ConquestState};
+use std::path::Path;
+use crate::model::Rules;
 
 pub struct BeginState {
     ids: Ids,
@@ -57,7 +59,30 @@ impl State for BeginState {
             .color(color::DARK_CHARCOAL)
             .label_color(color::GRAY)
             .set(self.ids.button_begin, ui) {
-            return Some(Box::new(ConquestState::new_game(ui.widget_id_generator())));
+            return Some(Box::new(ConquestState::new_game(ui.widget_id_generator(), Rules::new())));
+        }
+        for _ in widget::Button::new()
+            .w_h(200.0, 80.0)
+            .down_from(self.ids.button_begin, 10.0)
+            .label("Conquer at light speed")
+            .color(color::DARK_CHARCOAL)
+            .label_color(color::GRAY)
+            .set(self.ids.button_light_lag, ui) {
+            let mut rules = Rules::new();
+            rules.light_lag = true;
+            return Some(Box::new(ConquestState::new_game(ui.widget_id_generator(), rules)));
+        }
+        for _ in widget::Button::new()
+            .w_h(200.0, 80.0)
+            .down_from(self.ids.button_light_lag, 10.0)
+            .label("Watch the last game")
+            .color(color::DARK_CHARCOAL)
+            .label_color(color::GRAY)
+            .set(self.ids.button_replay, ui) {
+            match ConquestState::replay(ui.widget_id_generator(), Path::new(last_journal)) {
+                Ok(replay) => return Some(Box::new(replay)),
+                Err(message) => println!("can't replay: {}", message),
+            }
         }
         None
     }
@@ -67,6 +92,8 @@ widget_ids! {
     struct Ids {
         canvas_root,
         text_intro,
-        button_begin
+        button_begin,
+        button_light_lag,
+        button_replay
     }
 }
@@ -19,25 +19,31 @@
 use chrono::Duration;
 use conrod::widget::id::Generator;
 use conrod::{color, widget, widget_ids, Dimensions, UiCell};
+use piston_window::keyboard::Key;
 use piston_window::keyboard::Key::*;
 use piston_window::Button;
 use piston_window::Button::Keyboard;
 use piston_window::ButtonArgs;
+use piston_window::ButtonState::{Press, Release};
 use piston_window::Input;
 use piston_window::Motion::{MouseCursor, MouseScroll};
 use piston_window::MouseButton;
-use std::sync::{Arc, RwLock};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 use conrod::widget::primitive::shape::rectangle::Rectangle as WidgetRekt;
 
 use super::planet::PlanetState;
 use crate::camera::*;
 use crate::geometry::{Position, center, Rectangle};
-use crate::logic::logic_updater::Updater;
+use crate::logic::journal::{Journal, JournalWriter, Tape};
 use crate::logic::model_access::Change;
-use crate::logic::pulser::Pulser;
+use crate::logic::simulation::Simulation;
 use crate::logic::thread_status::Status;
-use crate::model::colony::*;
-use crate::model::galaxy::*;
+use crate::model::bookmark::Tracking;
+use crate::model::combat::Target;
+use crate::model::order::Order;
+use crate::model::scenario;
 use crate::model::ship::*;
 use crate::model::*;
 use crate::state::state_machine::{State, StateChange, StateEvent};
@@ -51,24 +57,48 @@ use conrod::Labelable;
 pub struct ConquestState {
     ids: Ids,
     camera: Camera,
-    updater: Updater,
-    pulser: Pulser,
+    simulation: Simulation,
     player_id: PlayerID,
     map_renderer: MapRenderer,
     last_mouse_position: Position,
     drag_mouse_start: Option<Position>,
     last_screen_size: Dimensions,
+    // held shift means orders get queued instead of replacing
+    shift_held: bool,
+    // held ctrl saves a bookmark under a number key instead of going to it
+    ctrl_held: bool,
+    // the search of the go to panel, None if it's closed
+    goto: Option<String>,
+    // right click in world coordinates, turned into an order during update
+    pending_right_click: Option<Position>,
+    // the last snapshot and what the player sees of it
+    view: Option<(Arc<GameModel>, Arc<GameModel>)>,
+    // playing a journal, orders are ignored
+    replaying: bool,
+    // the camera moves by how long frames take
+    last_frame: Instant,
+    // no edge scrolling when the mouse is outside the window
+    cursor_inside: bool,
 }
 
+// every new game is journaled here, the begin screen can play it again
+pub const last_journal: &'static str = "last_game.journal";
+
 impl State for ConquestState {
     fn enter(&mut self, _: Box<dyn State>) -> StateChange {
-        self.updater.start();
-        self.updater.controll.set_status(Status::Paused);
-        self.pulser.start();
+        // the simulation runs since the game started, coming back from
+        // a planet doesn't start another one
         None
     }
     fn poll_event(&self) -> StateEvent {
-        self.pulser.get_event()
+        let mouse = self.mouse_inside();
+        if self.simulation.pulser.take_pulse()
+            || self.camera.is_moving(self.last_screen_size, mouse)
+        {
+            StateEvent::WantsUpdate
+        } else {
+            StateEvent::Idle
+        }
     }
     fn update(&mut self, ui: &mut UiCell) -> StateChange {
         self.last_screen_size = ui.window_dim();
@@ -78,7 +108,7 @@ impl State for ConquestState {
             .crop_kids()
             .set(self.ids.canvas_root, ui);
 
-        let model = self.updater.model_writer.copy_model();
+        let model = self.perceived_model();
         let time = model.time;
 
         if let Some(rect) = self.ceate_dragtengle_maybe() {
@@ -89,13 +119,19 @@ impl State for ConquestState {
                 .set(self.ids.rect_select, ui);
         }
 
-        self.camera.position = self.camera.track_body.map_or(self.camera.position, |x| {
-            model.galaxy[x].calc_position(&time)
-        });
+        let now = Instant::now();
+        let passed = now.duration_since(self.last_frame);
+        self.last_frame = now;
+        // a hitch shouldn't throw the camera across the galaxy
+        let seconds = (passed.as_secs() as f64 + passed.subsec_nanos() as f64 * 1e-9).min(0.1);
+        let mouse = self.mouse_inside();
+        self.camera.update(seconds, self.last_screen_size, mouse);
+        self.camera.follow(seconds, &model);
 
         let projection = self.camera.create_projection(self.last_screen_size);
 
         self.map_renderer.render(ui, &projection, &model);
+        let mut right_clicked_body = None;
         for (body_address, view_id) in self.map_renderer.planets.map.iter().filter_map(|kv| {
             if let Some(view_id) = kv.1.get_view_id() {
                 Some((kv.0, view_id))
@@ -112,7 +148,8 @@ impl State for ConquestState {
                         should_return = true;
                     }
                     if buttons.right().is_down() {
-                        self.camera.track_body = Some(body_address.clone());
+                        self.camera.track(Tracking::Body(*body_address));
+                        right_clicked_body = Some(body_address.clone());
                     }
                 }
             }
@@ -120,12 +157,22 @@ impl State for ConquestState {
                 return Some(Box::new(PlanetState::new(
                     ui.widget_id_generator(),
                     body_address.clone(),
-                    self.updater.model_writer.clone(),
+                    self.player_id,
+                    self.simulation.handle(),
                 )));
             }
         }
 
-        let pausedlabel = match self.updater.controll.get_status() {
+        if let Some(destination) = self.pending_right_click.take() {
+            let order = match right_clicked_body {
+                Some(address) => Order::Orbit(address),
+                None => Order::MoveTo(destination),
+            };
+            self.simulation
+                .enqueue(Change::Order(self.player_id, order, self.shift_held));
+        }
+
+        let pausedlabel = match self.simulation.clock.controll.get_status() {
             Status::Paused => ">",
             _ => "❚❚",
         };
@@ -137,7 +184,7 @@ impl State for ConquestState {
             .label_color(color::GRAY)
             .set(self.ids.button_pause, ui)
         {
-            self.updater.controll.toggle_pause();
+            self.simulation.clock.controll.toggle_pause();
         }
 
         let mut previous = self.ids.button_pause;
@@ -158,7 +205,7 @@ impl State for ConquestState {
                 .label_color(color::GRAY)
                 .set(id, ui)
             {
-                self.updater.controll.set_pace(speed);
+                self.simulation.clock.controll.set_pace(speed);
             }
             previous = id;
         }
@@ -171,7 +218,7 @@ impl State for ConquestState {
             .label_color(color::GRAY)
             .set(self.ids.button_granu_weeks, ui)
         {
-            self.updater.set_granuality(Duration::weeks);
+            self.simulation.clock.set_granuality(Duration::weeks);
         }
         previous = self.ids.button_granu_weeks;
         let buttons: [(&'static str, fn(i64) -> Duration, _); 5] = [
@@ -195,47 +242,174 @@ impl State for ConquestState {
                 .label_color(color::GRAY)
                 .set(id, ui)
             {
-                self.updater.set_granuality(function);
+                self.simulation.clock.set_granuality(function);
             }
             previous = id;
         }
 
-        let money = format!(
-            "money: {} \n time: {}",
+        // a replay only goes forward, the journal is used up as it plays
+        let rewind_color = if self.replaying {
+            color::DARK_GREY
+        } else {
+            color::GRAY
+        };
+        for _ in widget::Button::new()
+            .w_h(30.0, 30.0)
+            .down_from(self.ids.button_granu_weeks, 10.0)
+            .align_right_of(self.ids.button_pause)
+            .label("<|")
+            .color(color::DARK_CHARCOAL)
+            .label_color(rewind_color)
+            .set(self.ids.button_step_back, ui)
+        {
+            self.step_back();
+        }
+        for _ in widget::Button::new()
+            .w_h(30.0, 30.0)
+            .left_from(self.ids.button_step_back, 10.0)
+            .label("<<")
+            .color(color::DARK_CHARCOAL)
+            .label_color(rewind_color)
+            .set(self.ids.button_rewind, ui)
+        {
+            self.rewind(Duration::weeks(4));
+        }
+
+        let mut money = format!(
+            "money: {} \n time: {} \n speed: {:.0} s/s",
             model.players[0].money,
-            time.num_weeks()
+            time.num_weeks(),
+            self.simulation.sim_seconds_per_second()
         );
+        if model.rules.light_lag {
+            let underway = model
+                .pending_orders
+                .iter()
+                .filter(|x| model.unit_owner(x.unit) == self.player_id)
+                .count();
+            money.push_str(&format!("\n orders underway: {}", underway));
+        }
+        if let Some(tracking) = self.camera.tracking {
+            money.push_str(&format!("\n following: {}", tracking.name(&model)));
+        }
+        if self.replaying {
+            money.push_str("\n replay, rewinding is off");
+        }
+        for failure in self.simulation.failures() {
+            money.push_str(&format!("\n {}", failure));
+        }
         widget::Text::new(&money)
             .color(color::LIGHT_RED)
             .top_left_with_margin_on(self.ids.canvas_root, 10.0)
             .left_justify()
             .line_spacing(10.0)
             .set(self.ids.text_money, ui);
+        self.goto_panel(ui, &model);
         None
     }
     fn input(&mut self, input: Input) -> StateChange {
         match input {
+            // the go to panel gets the keys while it's open
             Input::Button(ButtonArgs {
                 state: Press,
                 button: Keyboard(key),
                 ..
-            }) => match key {
-                W => self.camera.translate(MoveDirection::Up),
-                S => self.camera.translate(MoveDirection::Down),
-                A => self.camera.translate(MoveDirection::Left),
-                D => self.camera.translate(MoveDirection::Right),
-                Space => self.updater.controll.toggle_pause(),
-                Return => {
-                    self.camera.width = start_cam_width;
-                    self.camera.height = start_cam_height;
-                    self.camera.position = center;
-                    self.camera.stop_tracking();
+            }) if self.goto.is_none() => match key {
+                W => self.camera.hold(MoveDirection::Up, true),
+                S => self.camera.hold(MoveDirection::Down, true),
+                A => self.camera.hold(MoveDirection::Left, true),
+                D => self.camera.hold(MoveDirection::Right, true),
+                F => self.simulation.enqueue(Change::FormFleet(self.player_id)),
+                M => self.simulation.enqueue(Change::MergeFleets(self.player_id)),
+                X => self.simulation.enqueue(Change::SplitFleet(self.player_id)),
+                LShift | RShift => self.shift_held = true,
+                LCtrl | RCtrl => self.ctrl_held = true,
+                J => self.goto = Some(String::new()),
+                // with shift held every press adds a waypoint to the patrol
+                P => {
+                    let waypoint = self.mouse_to_world(self.last_mouse_position);
+                    self.give_order(Order::Patrol(vec![waypoint], 0));
+                }
+                E => {
+                    // everyone else in the selection escorts the first ship
+                    let escorted = self.simulation.snapshot().players
+                        [self.player_id]
+                        .selected
+                        .first()
+                        .cloned();
+                    if let Some(ship) = escorted {
+                        self.give_order(Order::Escort(ship));
+                    }
+                }
+                R => self.give_order(Order::Refuel(None)),
+                V => self.give_order(Order::Explore),
+                // invade whatever we're looking at, right click tracks a body
+                I => {
+                    if let Some(address) = self.camera.tracked_body() {
+                        self.give_order(Order::Invade(address));
+                    }
+                }
+                // mine the body we're looking at, or the cursor position
+                N => match self.camera.tracked_body() {
+                    Some(address) => self.give_order(Order::LayMines(Some(address))),
+                    None => {
+                        let spot = self.mouse_to_world(self.last_mouse_position);
+                        self.give_order(Order::MoveTo(spot));
+                        self.simulation
+                            .enqueue(Change::Order(self.player_id, Order::LayMines(None), true));
+                    }
+                },
+                // attack whatever foreign thing is under the cursor
+                K => {
+                    if let Some(target) = self.target_under_cursor() {
+                        self.give_order(Order::Attack(target));
+                    }
                 }
+                G => {
+                    if let Some(target) = self.target_under_cursor() {
+                        self.give_order(Order::FireMissiles(target));
+                    }
+                }
+                L => self
+                    .simulation
+                    .enqueue(Change::Order(self.player_id, Order::Loop, true)),
+                Space => self.simulation.clock.controll.toggle_pause(),
+                Backspace => self.step_back(),
+                Return => self.camera.reset(),
+                Q => self.camera.stop_tracking(),
+                Tab => self.track_next_selected(),
+                T => self.track_fleet_or_system(),
+                _ => {
+                    if let Some(slot) = bookmark_slot(key) {
+                        self.use_bookmark(slot);
+                    }
+                }
+            },
+            Input::Button(ButtonArgs {
+                state: Release,
+                button: Keyboard(key),
+                ..
+            }) => match key {
+                W => self.camera.hold(MoveDirection::Up, false),
+                S => self.camera.hold(MoveDirection::Down, false),
+                A => self.camera.hold(MoveDirection::Left, false),
+                D => self.camera.hold(MoveDirection::Right, false),
+                LShift | RShift => self.shift_held = false,
+                LCtrl | RCtrl => self.ctrl_held = false,
                 _ => {}
             },
-            Input::Move(MouseCursor(x, y)) => self.last_mouse_position = Position::new(x, y),
+            Input::Move(MouseCursor(x, y)) => {
+                self.last_mouse_position = Position::new(x, y);
+                self.camera
+                    .drag(self.last_screen_size, self.last_mouse_position);
+            }
+            Input::Cursor(inside) => self.cursor_inside = inside,
+            Input::Focus(false) => {
+                self.camera.release_all();
+                self.shift_held = false;
+                self.ctrl_held = false;
+            }
             Input::Move(MouseScroll(_, direction)) => self.camera.zoom(
-                self.last_screen_size,
                 if direction == 1.0 {
                     ZoomDirection::In
                 } else {
@@ -248,6 +422,26 @@ impl State for ConquestState {
                 button: Button::Mouse(MouseButton::Left),
                 ..
             }) => self.drag_mouse_start = Some(self.last_mouse_position),
+            // middle drag pans, right click is for orders
+            Input::Button(ButtonArgs {
+                state: Press,
+                button: Button::Mouse(MouseButton::Middle),
+                ..
+            }) => self
+                .camera
+                .grab(self.last_screen_size, self.last_mouse_position),
+            Input::Button(ButtonArgs {
+                state: Release,
+                button: Button::Mouse(MouseButton::Middle),
+                ..
+            }) => self.camera.let_go(),
+            Input::Button(ButtonArgs {
+                state: Press,
+                button: Button::Mouse(MouseButton::Right),
+                ..
+            }) => {
+                self.pending_right_click = Some(self.mouse_to_world(self.last_mouse_position));
+            }
             Input::Button(ButtonArgs {
                 state: Release,
                 button: Button::Mouse(MouseButton::Left),
@@ -259,11 +453,11 @@ impl State for ConquestState {
                         one: projection.screen_to_world(rect.one),
                         two: projection.screen_to_world(rect.two),
                     };
-                    let model_lock = self.updater.model_writer.read_lock_model();
+                    let model_lock = self.simulation.snapshot();
                     let time = model_lock.time;
                     let selected: Vec<ShipID> = model_lock
                         .ships
-                        .iter()
+                        .values()
                         .filter_map(|x| {
                             if x.owner != self.player_id {
                                 return None;
@@ -276,7 +470,7 @@ impl State for ConquestState {
                             }
                         })
                         .collect();
-                    self.updater
+                    self.simulation
                         .enqueue(Change::Select(self.player_id, selected));
                 };
                 self.drag_mouse_start = None
@@ -285,88 +479,236 @@ impl State for ConquestState {
         }
         None
     }
+    fn escape(&mut self) -> bool {
+        self.goto.take().is_some()
+    }
     fn exit(&mut self) {
-        // kill the threads
+        // the planet screen keeps using the simulation, its threads are
+        // stopped and joined once the game is dropped
         println!("exiting conquest state");
-        self.updater.stop();
-        self.pulser.controll.stop();
     }
 }
 
 impl ConquestState {
-    pub fn new_game(generator: Generator) -> ConquestState {
-        let earth = StellarBody::new_earthlike("earth");
+    pub fn new_game(generator: Generator, rules: Rules) -> ConquestState {
+        let mut model = scenario::sol();
+        model.rules = rules;
+        let journal = JournalWriter::create(Path::new(last_journal), "sol", rules)
+            .map_err(|e| println!("playing without a journal: {}", e))
+            .ok();
         ConquestState::new(
             generator,
             Camera::new(center, start_cam_width, start_cam_height),
-            Arc::new(RwLock::new(GameModel::new(vec![System::new(
-                center,
-                vec![
-                    StellarBody::create_single_star("sun"),
-                    StellarBody::new(
-                        BodyClass::Rocky(Colony::new_empty(0.147)),
-                        "mercury",
-                        Duration::days(88),
-                        0.387098,
-                    ),
-                    StellarBody::new(
-                        BodyClass::Rocky(Colony::new_empty(0.902)),
-                        "venus",
-                        Duration::days(225),
-                        0.723332,
-                    ),
-                    earth,
-                    StellarBody::new(
-                        BodyClass::Rocky(Colony::new_empty(0.284)),
-                        "mars",
-                        Duration::days(780),
-                        1.523679,
-                    ),
-                    StellarBody::new(
-                        BodyClass::GasGiant,
-                        "jupiter",
-                        Duration::days(4333),
-                        5.20260,
-                    ),
-                    StellarBody::new(
-                        BodyClass::GasGiant,
-                        "saturn",
-                        Duration::days(10759),
-                        9.554909,
-                    ),
-                    StellarBody::new(
-                        BodyClass::GasGiant,
-                        "uranus",
-                        Duration::days(30688),
-                        19.2184,
-                    ),
-                    StellarBody::new(
-                        BodyClass::GasGiant,
-                        "neptune",
-                        Duration::days(60182),
-                        30.110387,
-                    ),
-                ],
-            )]))),
+            model,
+            journal.map(Tape::Record),
         )
     }
+    // the game in the journal as it went, at whatever speed the clock goes
+    pub fn replay(generator: Generator, path: &Path) -> Result<ConquestState, String> {
+        let journal = Journal::read(path)?;
+        let model = journal.start_model()?;
+        Ok(ConquestState::new(
+            generator,
+            Camera::new(center, start_cam_width, start_cam_height),
+            model,
+            Some(journal.tape()),
+        ))
+    }
     pub fn new(
         generator: Generator,
         start_cam: Camera,
-        start_model: Arc<RwLock<GameModel>>,
+        start_model: GameModel,
+        tape: Option<Tape>,
     ) -> ConquestState {
+        let replaying = tape.as_ref().map_or(false, |x| x.is_playing());
+        let mut camera = start_cam;
+        camera.set_extent(start_model.galaxy.extent());
+        let player_id = 0;
         ConquestState {
             ids: Ids::new(generator),
-            player_id: 0,
-            camera: start_cam,
-            updater: Updater::new(start_model, Duration::days),
-            pulser: Pulser::new(StateEvent::Idle),
-            map_renderer: MapRenderer::new(),
+            player_id: player_id,
+            camera: camera,
+            simulation: Simulation::start(start_model, Duration::days, tape),
+            map_renderer: MapRenderer::new(player_id),
             last_mouse_position: center,
             drag_mouse_start: None,
             last_screen_size: init_dimensions,
+            shift_held: false,
+            ctrl_held: false,
+            goto: None,
+            pending_right_click: None,
+            view: None,
+            replaying: replaying,
+            last_frame: Instant::now(),
+            cursor_inside: true,
+        }
+    }
+    // back by that much game time, what happened since is forgotten
+    fn rewind(&self, by: Duration) {
+        if self.replaying {
+            return;
+        }
+        let model = self.simulation.snapshot();
+        self.simulation
+            .enqueue(Change::Rewind(model.time + model.pending_time - by));
+    }
+    // pauses and goes back one tick
+    fn step_back(&mut self) {
+        if self.replaying {
+            return;
+        }
+        self.simulation.clock.controll.set_status(Status::Paused);
+        let step = self.simulation.clock.step();
+        self.rewind(step);
+    }
+    // the selected ship after the one we're following, round and round
+    fn track_next_selected(&mut self) {
+        let model = self.simulation.snapshot();
+        let selected = &model.players[self.player_id].selected;
+        let next = match self.camera.tracking {
+            Some(Tracking::Ship(id)) => selected
+                .iter()
+                .position(|x| *x == id)
+                .map_or(0, |i| (i + 1) % selected.len()),
+            _ => 0,
+        };
+        if let Some(ship) = selected.get(next) {
+            self.camera.track(Tracking::Ship(*ship));
         }
     }
+    // the fleet of the selection, or the system under the cursor
+    fn track_fleet_or_system(&mut self) {
+        let model = self.simulation.snapshot();
+        let fleet = model.players[self.player_id]
+            .selected
+            .iter()
+            .filter_map(|x| model.ships.get(x).and_then(|ship| ship.fleet))
+            .next();
+        if let Some(fleet) = fleet {
+            return self.camera.track(Tracking::Fleet(fleet));
+        }
+        let cursor = self.mouse_to_world(self.last_mouse_position);
+        let system = model
+            .galaxy
+            .systems
+            .iter()
+            .position(|x| x.used_space.position.distance(cursor) <= x.used_space.radius);
+        if let Some(system) = system {
+            self.camera.track(Tracking::System(system));
+        }
+    }
+    // with ctrl held the view is saved under the number, otherwise we go back to it
+    fn use_bookmark(&mut self, slot: usize) {
+        if self.ctrl_held {
+            self.simulation.enqueue(Change::Bookmark(
+                self.player_id,
+                slot,
+                self.camera.bookmark(),
+            ));
+        } else if let Some(bookmark) = self.simulation.snapshot().players[self.player_id]
+            .bookmarks
+            .get(&slot)
+        {
+            self.camera.go_to_bookmark(bookmark);
+        }
+    }
+    // a search box with everything that matches below it, picking one
+    // flies the camera over to it
+    fn goto_panel(&mut self, ui: &mut UiCell, model: &GameModel) {
+        let query = match self.goto {
+            Some(ref query) => query.clone(),
+            None => return,
+        };
+        let destinations = model.destinations(self.player_id, &query);
+        let mut picked = None;
+        for event in widget::TextBox::new(&query)
+            .w_h(300.0, 30.0)
+            .mid_top_with_margin_on(self.ids.canvas_root, 50.0)
+            .font_size(14)
+            .color(color::DARK_CHARCOAL)
+            .text_color(color::GRAY)
+            .set(self.ids.goto_search, ui)
+        {
+            match event {
+                widget::text_box::Event::Update(text) => self.goto = Some(text),
+                // enter takes the first one
+                widget::text_box::Event::Enter => picked = destinations.first().map(|x| x.1),
+            }
+        }
+        for _ in widget::Button::new()
+            .w_h(30.0, 30.0)
+            .right_from(self.ids.goto_search, 5.0)
+            .label("x")
+            .color(color::DARK_CHARCOAL)
+            .label_color(color::GRAY)
+            .set(self.ids.button_goto_close, ui)
+        {
+            self.goto = None;
+        }
+        let rows = destinations.len().min(goto_rows).max(1);
+        let (mut items, scrollbar) = widget::List::flow_down(destinations.len())
+            .item_size(30.0)
+            .scrollbar_on_top()
+            .w_h(300.0, 30.0 * rows as f64)
+            .down_from(self.ids.goto_search, 5.0)
+            .set(self.ids.goto_list, ui);
+        while let Some(item) = items.next(ui) {
+            let button = widget::Button::new()
+                .label(&destinations[item.i].0)
+                .color(color::DARK_CHARCOAL)
+                .label_color(color::GRAY);
+            for _ in item.set(button, ui) {
+                picked = Some(destinations[item.i].1);
+            }
+        }
+        if let Some(scrollbar) = scrollbar {
+            scrollbar.set(ui);
+        }
+        if let Some(target) = picked {
+            self.camera.go_to(target, target.view_width(model));
+            self.goto = None;
+        }
+    }
+    fn give_order(&self, order: Order) {
+        self.simulation
+            .enqueue(Change::Order(self.player_id, order, self.shift_held));
+    }
+    fn target_under_cursor(&mut self) -> Option<Target> {
+        let cursor = self.mouse_to_world(self.last_mouse_position);
+        let within = self.camera.width / self.last_screen_size[0] * pick_pixels;
+        self.perceived_model()
+            .nearest_target(self.player_id, cursor, within)
+    }
+    // what the player sees of the latest snapshot, only worked out again
+    // once the writer published a new one
+    fn perceived_model(&mut self) -> Arc<GameModel> {
+        let snapshot = self.simulation.snapshot();
+        if let Some((ref seen, ref perceived)) = self.view {
+            if Arc::ptr_eq(seen, &snapshot) {
+                return perceived.clone();
+            }
+        }
+        let perceived = Arc::new(
+            (*snapshot)
+                .clone()
+                .perceived_by(self.player_id)
+                .interpolated(),
+        );
+        self.view = Some((snapshot, perceived.clone()));
+        perceived
+    }
+    // None when it's outside the window
+    fn mouse_inside(&self) -> Option<Position> {
+        if self.cursor_inside {
+            Some(self.last_mouse_position)
+        } else {
+            None
+        }
+    }
+    fn mouse_to_world(&self, mouse_position: Position) -> Position {
+        self.camera
+            .mouse_to_world(self.last_screen_size, mouse_position)
+    }
     fn ceate_dragtengle_maybe(&self) -> Option<Rectangle> {
         if let Some(drag_start) = self.drag_mouse_start {
             Some(Rectangle {
@@ -379,6 +721,28 @@ impl ConquestState {
     }
 }
 const init_dimensions: Dimensions = [0.0, 0.0];
+// how close in pixels the cursor has to be to pick something
+const pick_pixels: f64 = 10.0;
+
+// the number keys hold bookmarks
+fn bookmark_slot(key: Key) -> Option<usize> {
+    let slot = match key {
+        D0 => 0,
+        D1 => 1,
+        D2 => 2,
+        D3 => 3,
+        D4 => 4,
+        D5 => 5,
+        D6 => 6,
+        D7 => 7,
+        D8 => 8,
+        D9 => 9,
+        _ => return None,
+    };
+    Some(slot)
+}
+// the go to panel shows this many at once, the rest is scrolled to
+const goto_rows: usize = 12;
 
 widget_ids! {
     struct Ids {
@@ -395,7 +759,12 @@ widget_ids! {
         button_granu_minutes,
         button_granu_seconds,
         button_granu_milliseconds,
+        button_step_back,
+        button_rewind,
         text_money,
         rect_select,
+        goto_search,
+        button_goto_close,
+        goto_list,
     }
 }
@@ -21,38 +21,74 @@ use conrod;
 use conrod::{color, widget, widget_ids, Colorable, Labelable, Positionable, Sizeable, Widget};
 use std::sync::Arc;
 
-use crate::logic::model_access::{Change, ModelAccess};
+use crate::logic::model_access::Change;
+use crate::logic::simulation::SimulationHandle;
 use crate::model::galaxy::{BodyAddress, BodyClass};
-use crate::model::ship::Ship;
+use crate::model::colony::Constructable;
+use crate::model::ship::{Component, LagrangePoint, Ship};
+use crate::model::station::{Anchor, Station, StationKind};
+use crate::model::{GameModel, PlayerID};
 use crate::state::state_machine::{State, StateChange};
-use std::sync::mpsc::Sender;
 
 pub struct PlanetState {
     ids: Ids,
     subject: BodyAddress,
+    player_id: PlayerID,
     previous_state: Option<Box<dyn State>>,
-    model_access: ModelAccess,
-    change_queue: Option<Sender<Change>>,
+    simulation: SimulationHandle,
+    // where new stations go
+    anchor: Anchor,
+    // the last snapshot and what the player sees of it
+    view: Option<(Arc<GameModel>, Arc<GameModel>)>,
 }
 impl PlanetState {
     pub fn new(
         generator: conrod::widget::id::Generator,
         subject: BodyAddress,
-        model_access: ModelAccess,
+        player_id: PlayerID,
+        simulation: SimulationHandle,
     ) -> PlanetState {
         PlanetState {
             ids: Ids::new(generator),
             subject: subject,
+            player_id: player_id,
             previous_state: None,
-            model_access: model_access,
-            change_queue: None,
+            simulation: simulation,
+            anchor: Anchor::Orbit,
+            view: None,
         }
     }
 }
+impl PlanetState {
+    fn construct<T: Constructable + Send + Sync + 'static>(&self, constructable: T) {
+        self.simulation
+            .enqueue(Change::Construct(Arc::new(constructable), self.subject));
+    }
+    fn construct_station(&self, kind: StationKind, price: i64) {
+        self.construct(Station::new(
+            self.player_id,
+            price,
+            kind,
+            self.anchor,
+            self.subject,
+        ));
+    }
+    // worked out again only when a new snapshot was published
+    fn perceived_model(&mut self) -> Arc<GameModel> {
+        let snapshot = self.simulation.snapshot();
+        if let Some((ref seen, ref perceived)) = self.view {
+            if Arc::ptr_eq(seen, &snapshot) {
+                return perceived.clone();
+            }
+        }
+        let perceived = Arc::new((*snapshot).clone().perceived_by(self.player_id));
+        self.view = Some((snapshot, perceived.clone()));
+        perceived
+    }
+}
 impl State for PlanetState {
     fn enter(&mut self, previous: Box<dyn State>) -> StateChange {
         self.previous_state = Some(previous);
-        self.change_queue = Some(self.model_access.start());
         None
     }
     fn update(&mut self, ui: &mut conrod::UiCell) -> StateChange {
@@ -60,7 +96,10 @@ impl State for PlanetState {
         widget::Canvas::new()
             .color(color::BLACK)
             .set(self.ids.canvas_root, ui);
-        let body = self.model_access.read_lock_model().galaxy[self.subject].clone(); // clone to descope lock
+        // only show what the player knows
+        let model = self.perceived_model();
+        let surveyed = model.is_surveyed(self.player_id, self.subject);
+        let body = model.galaxy[self.subject].clone();
         let bodyinfo = match &body.class {
             &BodyClass::Rocky(ref habitat) => {
                 let head_count = if let Some(ref pop) = habitat.population {
@@ -73,10 +112,64 @@ impl State for PlanetState {
             &BodyClass::GasGiant => ("gass giant", 0),
             &BodyClass::Star => ("star", 0),
         };
-        let text = format!(
+        let mut text = format!(
             "{} is a {} \n population {}",
             body.name, bodyinfo.0, bodyinfo.1
         );
+        match (&body.class, surveyed) {
+            (&BodyClass::Rocky(ref habitat), true) => text.push_str(&format!(
+                "\n size {:.3} earths \n habitability {:.2} \n metal {:.2}, rare earth {:.2}, fuel {:.2}",
+                habitat.size,
+                habitat.habitability,
+                habitat.deposits.metal,
+                habitat.deposits.rare_earth,
+                habitat.deposits.fuel
+            )),
+            (&BodyClass::Rocky(_), false) => text.push_str("\n not surveyed"),
+            _ => {}
+        }
+        if let BodyClass::Rocky(ref habitat) = body.class {
+            if habitat.owner == Some(self.player_id) {
+                text.push_str(&format!("\n garrison {:.0}", habitat.garrison));
+                if let Some(ref pop) = habitat.population {
+                    text.push_str(&format!(
+                        "\n loyalty {:.2}, unrest {:.2}",
+                        pop.loyalty, pop.unrest
+                    ));
+                    let shares = pop.cohorts.shares;
+                    text.push_str(&format!(
+                        "\n children {:.0}%, adults {:.0}%, older {:.0}%, elderly {:.0}%",
+                        shares[0] * 100.0,
+                        shares[1] * 100.0,
+                        shares[2] * 100.0,
+                        shares[3] * 100.0
+                    ));
+                }
+                // worked out in the background, it's there after a moment
+                if let Some(forecast) = self.simulation.forecast() {
+                    if let Some(ready) = forecast.next_ready(self.subject, model.time) {
+                        text.push_str(&format!(
+                            "\n {} queued, next ready in {} weeks",
+                            habitat.construction_queue.len(),
+                            ready.num_weeks()
+                        ));
+                    }
+                    if let Some(head_count) = forecast.population.get(&self.subject) {
+                        text.push_str(&format!(
+                            "\n population in {} months {}",
+                            (forecast.until - forecast.from).num_days() * 12 / 365,
+                            head_count
+                        ));
+                    }
+                }
+            }
+            if let Some(ref battle) = habitat.battle {
+                text.push_str(&format!(
+                    "\n ground battle, {:.0} invaders left",
+                    battle.troops
+                ));
+            }
+        }
         widget::Text::new(&text)
             .color(color::LIGHT_RED)
             .middle_of(self.ids.canvas_root)
@@ -96,7 +189,7 @@ impl State for PlanetState {
             return stored;
         }
         if let BodyClass::Rocky(habitat) = body.class {
-            if let Some(owner) = habitat.owner {
+            if habitat.owner == Some(self.player_id) {
                 for _ in widget::Button::new()
                     .w_h(200.0, 80.0)
                     .label("build ship")
@@ -104,13 +197,149 @@ impl State for PlanetState {
                     .label_color(color::GRAY)
                     .set(self.ids.build_ship, ui)
                 {
-                    println!("building for {}", owner);
-                    self.change_queue.clone().map(|x| {
-                        x.send(Change::Construct(
-                            Arc::new(Ship::new(0, 1000, self.subject)),
-                            self.subject,
-                        ))
-                    });
+                    println!("building for {}", self.player_id);
+                    self.construct(Ship::new(self.player_id, 1000, self.subject));
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_ship, 10.0)
+                    .label("build survey ship")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_survey_ship, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 1500, self.subject)
+                            .with_component(Component::Survey),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_survey_ship, 10.0)
+                    .label("build troop transport")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_troop_transport, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 2000, self.subject)
+                            .with_component(Component::Troops(troops_per_transport)),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_troop_transport, 10.0)
+                    .label("build warship")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_warship, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 2500, self.subject)
+                            .with_component(Component::Cannon(cannon_damage)),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_warship, 10.0)
+                    .label("build minelayer")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_minelayer, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 2000, self.subject)
+                            .with_component(Component::MineLayer(mines_per_layer)),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_minelayer, 10.0)
+                    .label("build minesweeper")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_minesweeper, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 1500, self.subject)
+                            .with_component(Component::MineSweeper(mines_swept)),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_minesweeper, 10.0)
+                    .label("build missile boat")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_missile_boat, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 3000, self.subject)
+                            .with_component(Component::MissileLauncher(missiles_per_launcher))
+                            .with_component(Component::MissileLauncher(missiles_per_launcher)),
+                    );
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_missile_boat, 10.0)
+                    .label("build escort")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_escort, ui)
+                {
+                    self.construct(
+                        Ship::new(self.player_id, 2000, self.subject)
+                            .with_component(Component::PointDefence(point_defence_chance)),
+                    );
+                }
+                let anchor_label = match self.anchor {
+                    Anchor::Orbit => "stations go in orbit",
+                    Anchor::Lagrange(LagrangePoint::L4) => "stations go to L4",
+                    Anchor::Lagrange(LagrangePoint::L5) => "stations go to L5",
+                };
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .right_from(self.ids.build_ship, 10.0)
+                    .label(anchor_label)
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.station_anchor, ui)
+                {
+                    self.anchor = match self.anchor {
+                        Anchor::Orbit => Anchor::Lagrange(LagrangePoint::L4),
+                        Anchor::Lagrange(LagrangePoint::L4) => Anchor::Lagrange(LagrangePoint::L5),
+                        Anchor::Lagrange(LagrangePoint::L5) => Anchor::Orbit,
+                    };
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.station_anchor, 10.0)
+                    .label("build habitat")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_habitat, ui)
+                {
+                    self.construct_station(StationKind::Habitat, 5000);
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_habitat, 10.0)
+                    .label("build comm satellite")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_comm_satellite, ui)
+                {
+                    self.construct_station(StationKind::CommSatellite, 2000);
+                }
+                for _ in widget::Button::new()
+                    .w_h(200.0, 80.0)
+                    .down_from(self.ids.build_comm_satellite, 10.0)
+                    .label("build supply depot")
+                    .color(color::DARK_CHARCOAL)
+                    .label_color(color::GRAY)
+                    .set(self.ids.build_depot, ui)
+                {
+                    self.construct_station(StationKind::Depot, 3000);
                 }
             }
         }
@@ -118,12 +347,33 @@ impl State for PlanetState {
     }
 }
 
+const troops_per_transport: f64 = 50_000.0;
+// hull damage per hour
+const cannon_damage: f64 = 10.0;
+const mines_per_layer: f64 = 200.0;
+// mines cleared per hour
+const mines_swept: f64 = 2.0;
+const missiles_per_launcher: f64 = 6.0;
+// chance to down a missile in range every missile step
+const point_defence_chance: f64 = 0.05;
+
 // Generate a unique `WidgetId` for each widget.
 widget_ids! {
     struct Ids {
         canvas_root,
         text_intro,
         button_begin,
-        build_ship
+        build_ship,
+        build_survey_ship,
+        build_troop_transport,
+        build_warship,
+        build_minelayer,
+        build_minesweeper,
+        build_missile_boat,
+        build_escort,
+        station_anchor,
+        build_habitat,
+        build_comm_satellite,
+        build_depot
     }
 }
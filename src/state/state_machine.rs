@@ -37,6 +37,11 @@ pub trait State {
     fn input(&mut self, _: Input) -> StateChange {
         None
     }
+    // escape was pressed, true if something was open and got closed.
+    // otherwise escape closes the game
+    fn escape(&mut self) -> bool {
+        false
+    }
 
     // this function allows a state to send commands to the main render loop
     // rihgt now only used to ask for regular render updates
@@ -76,6 +81,9 @@ impl StateMachine {
             self.change_state(statebox);
         }
     }
+    pub fn escape(&mut self) -> bool {
+        self.state.escape()
+    }
     // allows seperate treats managed by the state
     // to ask for simple stuff such as updates
     pub fn poll_events(&self) -> StateEvent {
@@ -21,11 +21,17 @@
 
 use petgraph::graph::NodeIndex;
 use conrod::widget::primitive::shape::oval::Full;
-use conrod::widget::{Oval};
+use conrod::widget::{Oval, PointPath};
 use conrod::*;
 
 use crate::model::*;
 use crate::model::ship::ShipID;
+use crate::model::fleet::FleetID;
+use crate::model::order::Unit;
+use crate::model::sensor::ContactID;
+use crate::model::station::StationID;
+use crate::model::mines::MinefieldID;
+use crate::model::missile::MissileID;
 use crate::model::galaxy::*;
 use crate::geometry::Position;
 use crate::camera::Projection;
@@ -101,26 +107,59 @@ use conrod::widget::Rectangle;
 pub struct MapRenderer{
     pub planets:ViewsMap<BodyAddress, Oval<Full>, PlanetView>,
     ships:ViewsMap<ShipID, Oval<Full>, ShipView>,
-    selected:ViewsMap<usize,Rectangle,SelectionView>,
+    stations:ViewsMap<StationID, Rectangle, StationView>,
+    minefields:ViewsMap<MinefieldID, Oval<Full>, MinefieldView>,
+    missiles:ViewsMap<MissileID, Oval<Full>, MissileView>,
+    selected:ViewsMap<(PlayerID, usize),Rectangle,SelectionView>,
+    fleets:ViewsMap<FleetID,Rectangle,FleetView>,
+    paths:ViewsMap<Unit,PointPath<Vec<Point>>,OrderPathView>,
+    contacts:ViewsMap<(PlayerID, ContactID),Rectangle,StaleContactView>,
     player:PlayerID
 }
 impl MapRenderer{
-    pub fn new() -> MapRenderer{
+    // draws what the player sees, their selection and their stale contacts
+    pub fn new(player:PlayerID) -> MapRenderer{
         MapRenderer{
             planets:ViewsMap::<BodyAddress, Oval<Full>, PlanetView>::new(PlanetView::new),
             ships:ViewsMap::<ShipID, Oval<Full>, ShipView>::new(ShipView::new),
-            selected:ViewsMap::<usize,Rectangle,SelectionView>::new(SelectionView::new),
-            player:0
+            stations:ViewsMap::<StationID, Rectangle, StationView>::new(StationView::new),
+            minefields:ViewsMap::<MinefieldID, Oval<Full>, MinefieldView>::new(MinefieldView::new),
+            missiles:ViewsMap::<MissileID, Oval<Full>, MissileView>::new(MissileView::new),
+            selected:ViewsMap::<(PlayerID, usize),Rectangle,SelectionView>::new(SelectionView::new),
+            fleets:ViewsMap::<FleetID,Rectangle,FleetView>::new(FleetView::new),
+            paths:ViewsMap::<Unit,PointPath<Vec<Point>>,OrderPathView>::new(OrderPathView::new),
+            contacts:ViewsMap::<(PlayerID, ContactID),Rectangle,StaleContactView>::new(StaleContactView::new),
+            player:player
         }
     }
+    // the game_state should be perceived by the player, so we only draw what
+    // the player knows about
     pub fn render(&mut self, ui:&mut conrod::UiCell, projection:&Projection, game_state:&GameModel){
         self.planets.update_views(game_state.galaxy.systems.iter().filter(
             |x| projection.is_visible(&x.used_space)
         ).flat_map(|x| x.bodies.iter().map(|y| y.address)));
         self.planets.render(ui,projection,game_state);
-        self.ships.update_views(game_state.ships.iter().enumerate().map(|x| x.0));
+        self.minefields.update_views(game_state.minefields.keys().cloned());
+        self.minefields.render(ui,projection,game_state);
+        self.ships.update_views(game_state.ships.keys().cloned());
         self.ships.render(ui,projection,game_state);
-        self.selected.update_views(game_state.players[self.player].selected.iter().enumerate().map(|x| x.0));
+        self.stations.update_views(game_state.stations.keys().cloned());
+        self.stations.render(ui,projection,game_state);
+        self.missiles.update_views(game_state.missiles.keys().cloned());
+        self.missiles.render(ui,projection,game_state);
+        self.fleets.update_views(game_state.fleets.keys().cloned());
+        self.fleets.render(ui,projection,game_state);
+        let player = self.player;
+        self.paths.update_views(
+            game_state.fleets.values().filter(|x| x.owner == player && !x.orders.is_idle())
+                .map(|x| Unit::Fleet(x.id))
+            .chain(game_state.ships.values().filter(|x| x.owner == player && x.fleet.is_none() && !x.orders.is_idle())
+                .map(|x| Unit::Ship(x.id)))
+        );
+        self.paths.render(ui,projection,game_state);
+        self.contacts.update_views(game_state.players[player].contacts.keys().map(|x| (player, *x)));
+        self.contacts.render(ui,projection,game_state);
+        self.selected.update_views(game_state.players[player].selected.iter().enumerate().map(|x| (player, x.0)));
         self.selected.render(ui,projection,game_state);
     }
     
@@ -147,11 +186,185 @@ impl View<Oval<Full>> for ShipView{
         self.view_id = Some(id);
     }
     fn get_world_position(&self, game_state:&GameModel) -> Position{
-        game_state.ships[self.ship_id].movement.calc_position(&game_state.time, &game_state.galaxy)
+        game_state.ships[&self.ship_id].movement.calc_position(&game_state.time, &game_state.galaxy)
     }
     fn get_widget(&self) -> Oval<Full>{
         Oval::fill([5.0,5.0]).color(black)
     }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // out of sensor range, or gone
+        let ship = match game_state.ships.get(&self.ship_id) {
+            Some(ship) => ship,
+            None => return false
+        };
+        // zoomed out fleet members are drawn as a single fleet marker
+        if is_fleet_zoom(projection) && ship.fleet.is_some(){
+            return false;
+        }
+        projection.is_pos_visible(&self.get_world_position(game_state))
+    }
+}
+
+struct StationView{
+    view_id:Option<NodeIndex<u32>>,
+    station_id:StationID
+}
+impl StationView{
+    fn new(id:StationID) -> StationView{
+        StationView{
+            view_id:None,
+            station_id:id
+        }
+    }
+}
+const station_color:Color = Color::Rgba(0.2,0.2,0.8,1.0);
+impl View<Rectangle> for StationView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        game_state.stations[&self.station_id].calc_position(&game_state.time, &game_state.galaxy)
+    }
+    fn get_widget(&self) -> Rectangle{
+        Rectangle::fill([6.0,6.0]).color(station_color)
+    }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // destroyed or out of sensor range
+        game_state.stations.contains_key(&self.station_id)
+            && projection.is_pos_visible(&self.get_world_position(game_state))
+    }
+}
+
+struct MissileView{
+    view_id:Option<NodeIndex<u32>>,
+    missile_id:MissileID
+}
+impl MissileView{
+    fn new(id:MissileID) -> MissileView{
+        MissileView{
+            view_id:None,
+            missile_id:id
+        }
+    }
+}
+const missile_color:Color = Color::Rgba(1.0,0.5,0.0,1.0);
+impl View<Oval<Full>> for MissileView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        game_state.missiles[&self.missile_id].position
+    }
+    fn get_widget(&self) -> Oval<Full>{
+        Oval::fill([3.0,3.0]).color(missile_color)
+    }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // exploded, shot down or out of sensor range
+        game_state.missiles.contains_key(&self.missile_id)
+            && projection.is_pos_visible(&self.get_world_position(game_state))
+    }
+}
+
+// a circle as big as the field on the map
+struct MinefieldView{
+    view_id:Option<NodeIndex<u32>>,
+    field_id:MinefieldID
+}
+impl MinefieldView{
+    fn new(id:MinefieldID) -> MinefieldView{
+        MinefieldView{
+            view_id:None,
+            field_id:id
+        }
+    }
+}
+const minefield_color:Color = Color::Rgba(0.8,0.2,0.2,0.6);
+// keep it visible when zoomed out
+const minefield_min_pixels:f64 = 4.0;
+impl View<Oval<Full>> for MinefieldView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        game_state.minefields[&self.field_id].calc_position(&game_state.time, &game_state.galaxy)
+    }
+    fn get_widget(&self) -> Oval<Full>{
+        Oval::outline([minefield_min_pixels, minefield_min_pixels]).color(minefield_color)
+    }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // swept away or out of sensor range
+        game_state.minefields.contains_key(&self.field_id)
+            && projection.is_pos_visible(&self.get_world_position(game_state))
+    }
+    // the size depends on the zoom level
+    fn render(
+        &mut self,
+        ui:&mut conrod::UiCell,
+        projection:&Projection,
+        game_state:&GameModel
+    ) {
+        let world = self.get_world_position(game_state);
+        let radius = game_state.minefields[&self.field_id].radius;
+        let position = projection.world_to_screen(world);
+        let edge = projection.world_to_screen(Position::new(world.x + radius, world.y));
+        let diameter = ((edge.x - position.x).abs() * 2.0).max(minefield_min_pixels);
+        let view_id = self.get_view_id().unwrap_or_else(|| {
+            let id = ui.widget_id_generator().next();
+            self.set_view_id(id);
+            id
+        });
+        Oval::outline([diameter, diameter]).color(minefield_color)
+            .x(position.x).y(position.y).set(view_id, ui);
+    }
+}
+
+// from this viewport width on fleets get drawn as one marker
+const fleet_marker_zoom:Au = 0.001;
+fn is_fleet_zoom(projection:&Projection) -> bool{
+    projection.view_port.width() > fleet_marker_zoom
+}
+struct FleetView{
+    view_id:Option<NodeIndex<u32>>,
+    fleet_id:FleetID
+}
+impl FleetView{
+    fn new(id:FleetID) -> FleetView{
+        FleetView{
+            view_id:None,
+            fleet_id:id
+        }
+    }
+}
+impl View<Rectangle> for FleetView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        let flagship = game_state.fleets[&self.fleet_id].flagship;
+        game_state.ships[&flagship].movement.calc_position(&game_state.time, &game_state.galaxy)
+    }
+    fn get_widget(&self) -> Rectangle{
+        Rectangle::fill([8.0,8.0]).color(black)
+    }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // merged fleets stick around in the views map
+        if !game_state.fleets.contains_key(&self.fleet_id){
+            return false;
+        }
+        is_fleet_zoom(projection) && projection.is_pos_visible(&self.get_world_position(game_state))
+    }
 }
 pub struct PlanetView{
     view_id:Option<NodeIndex<u32>>,
@@ -177,14 +390,71 @@ impl View<Oval<Full>> for PlanetView{
     }
 }
 
+// draws the queued orders of a ship or fleet as a line
+struct OrderPathView{
+    view_id:Option<NodeIndex<u32>>,
+    unit:Unit
+}
+impl OrderPathView{
+    fn new(unit:Unit) -> OrderPathView{
+        OrderPathView{
+            view_id:None,
+            unit:unit
+        }
+    }
+    fn unit_exists(&self, game_state:&GameModel) -> bool{
+        match self.unit {
+            Unit::Ship(ship) => game_state.ships.get(&ship).map_or(false, |x| x.fleet.is_none()),
+            Unit::Fleet(fleet) => game_state.fleets.contains_key(&fleet),
+        }
+    }
+}
+const path_color:Color = Color::Rgba(0.0,1.0,0.0,0.6);
+impl View<PointPath<Vec<Point>>> for OrderPathView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        game_state.unit_position(self.unit)
+    }
+    fn get_widget(&self) -> PointPath<Vec<Point>>{
+        PointPath::abs(Vec::new())
+    }
+    fn is_visible(&self, _:&Projection, game_state:&GameModel) -> bool{
+        // the line may cross the screen even if both ends are outside
+        self.unit_exists(game_state) && !game_state.unit_orders(self.unit).is_idle()
+    }
+    // a path isn't positioned by a single point, so we set all of them
+    fn render(
+        &mut self,
+        ui:&mut conrod::UiCell,
+        projection:&Projection,
+        game_state:&GameModel
+    ) {
+        let points:Vec<Point> = game_state.order_waypoints(self.unit).into_iter().map(|x| {
+            let screen = projection.world_to_screen(x);
+            [screen.x, screen.y]
+        }).collect();
+        let view_id = self.get_view_id().unwrap_or_else(|| {
+            let id = ui.widget_id_generator().next();
+            self.set_view_id(id);
+            id
+        });
+        PointPath::abs(points).color(path_color).thickness(1.0).set(view_id, ui);
+    }
+}
+
 struct SelectionView{
     view_id:Option<NodeIndex<u32>>,
     selected_index:usize,
     player_id:PlayerID,
 }
 impl SelectionView{
-    fn new(address:usize)->SelectionView{
-        SelectionView{view_id:None,selected_index:address, player_id:0}
+    fn new((player, address):(PlayerID, usize))->SelectionView{
+        SelectionView{view_id:None,selected_index:address, player_id:player}
     }
 }
 impl View<Rectangle> for SelectionView{
@@ -196,11 +466,52 @@ impl View<Rectangle> for SelectionView{
     }
     fn get_world_position(&self, game_state:&GameModel) -> Position{
         let ship_id = game_state.players[self.player_id].selected[self.selected_index];
-        game_state.ships[ship_id].movement.calc_position(
+        game_state.ships[&ship_id].movement.calc_position(
             &game_state.time, &game_state.galaxy
         )
     }
     fn get_widget(&self) -> Rectangle{
         Rectangle::outline([10.0,10.0])
     }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        let selected = &game_state.players[self.player_id].selected;
+        if self.selected_index >= selected.len() || !game_state.ships.contains_key(&selected[self.selected_index]){
+            return false;
+        }
+        projection.is_pos_visible(&self.get_world_position(game_state))
+    }
+}
+
+// where we last saw something that's out of sensor range now
+struct StaleContactView{
+    view_id:Option<NodeIndex<u32>>,
+    contact:ContactID,
+    player_id:PlayerID,
+}
+impl StaleContactView{
+    fn new((player, contact):(PlayerID, ContactID)) -> StaleContactView{
+        StaleContactView{view_id:None, contact:contact, player_id:player}
+    }
+}
+const stale_color:Color = Color::Rgba(0.5,0.5,0.5,0.8);
+impl View<Rectangle> for StaleContactView{
+    fn get_view_id(&self)-> Option<NodeIndex<u32>>{
+        self.view_id
+    }
+    fn set_view_id(&mut self, id:NodeIndex<u32>){
+        self.view_id = Some(id);
+    }
+    fn get_world_position(&self, game_state:&GameModel) -> Position{
+        game_state.players[self.player_id].contacts[&self.contact].position
+    }
+    fn get_widget(&self) -> Rectangle{
+        Rectangle::outline([6.0,6.0]).color(stale_color)
+    }
+    fn is_visible(&self, projection:&Projection, game_state:&GameModel) -> bool{
+        // current contacts are drawn as the real thing
+        match game_state.players[self.player_id].contacts.get(&self.contact) {
+            Some(contact) if !contact.current => projection.is_pos_visible(&contact.position),
+            _ => false
+        }
+    }
 }